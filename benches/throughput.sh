@@ -0,0 +1,63 @@
+#!/usr/bin/env bash
+# Compares the throughput of gen builds, e.g. before and after a change:
+#
+#   git stash && cargo build --release && cp target/release/gen /tmp/gen-before
+#   git stash pop && cargo build --release
+#   benches/throughput.sh /tmp/gen-before target/release/gen
+#
+# Each case is run RUNS times (3 by default) and the fastest run is reported.
+# Output goes to OUT (/dev/null by default), set it to a file to include the disk.
+# Set PIPE=1 to write through a pipe to cat instead, like `gen ... | consumer`.
+
+set -euo pipefail
+
+if [ $# -eq 0 ]; then
+    echo "Usage: $0 <gen binary>..." >&2
+    exit 1
+fi
+
+RUNS=${RUNS:-3}
+OUT=${OUT:-/dev/null}
+PIPE=${PIPE:-0}
+
+CASES=(
+    "int -n 50M"
+    "float -n 20M"
+    "uuid -n 10M"
+    "url -n 2M"
+    "ascii -s 2GB"
+    "bytes -s 2GB"
+    "unicode utf8 -s 500MB"
+)
+
+printf '%-24s' "case"
+for bin in "$@"; do
+    printf '%16s' "$(basename "$bin")"
+done
+printf '\n'
+
+for case in "${CASES[@]}"; do
+    printf '%-24s' "$case"
+
+    for bin in "$@"; do
+        best=""
+
+        for _ in $(seq "$RUNS"); do
+            start=$(date +%s.%N)
+            # shellcheck disable=SC2086
+            if [ "$PIPE" = 1 ]; then
+                "$bin" $case | cat > "$OUT"
+            else
+                "$bin" $case > "$OUT"
+            fi
+            end=$(date +%s.%N)
+
+            best=$(awk -v start="$start" -v end="$end" -v best="$best" \
+                'BEGIN { time = end - start; print (best == "" || time < best) ? time : best }')
+        done
+
+        printf '%15.2fs' "$best"
+    done
+
+    printf '\n'
+done
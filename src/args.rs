@@ -1,6 +1,19 @@
-use std::{fmt::Display, num::NonZeroUsize, path::PathBuf, str::FromStr};
-
-use clap::{error::ErrorKind, ArgGroup, Error, Parser, Subcommand};
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    num::{NonZeroU32, NonZeroUsize},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use clap::{ArgGroup, Parser, Subcommand};
+
+use crate::{
+    charset::Charset,
+    regex::Pattern,
+    template::Template,
+    unicode_tables::{Block, GeneralCategory, Script},
+};
 
 /// Generate random data.
 #[derive(Debug, Clone, Parser)]
@@ -11,9 +24,118 @@ pub struct GenArgs {
     #[arg(long, requires("destination"))]
     pub daemon: bool,
 
-    /// The destination file to write to. If not provided, write to stdout.
-    #[arg(short, long)]
-    pub destination: Option<PathBuf>,
+    /// The destination to write to. If not provided, write to stdout.
+    /// Either a file path or a network address:
+    ///   tcp://<host>:<port>         Connect and stream over TCP.
+    ///   tcp-listen://<host>:<port>  Wait for a TCP connection and stream to it.
+    ///   udp://<host>:<port>         Send the output as UDP datagrams.
+    #[arg(short, long, verbatim_doc_comment)]
+    pub destination: Option<Destination>,
+
+    /// The payload size of each UDP datagram.
+    /// Only applies to udp:// destinations.
+    /// Default: 1472B.
+    #[arg(long, verbatim_doc_comment)]
+    pub datagram_size: Option<DatagramSize>,
+
+    /// The maximum number of UDP datagrams to send per second.
+    /// Only applies to udp:// destinations.
+    #[arg(long, verbatim_doc_comment)]
+    pub packets_per_second: Option<NonZeroU32>,
+
+    /// Compress the output. Possible values: gzip, zstd, xz, lz4, none.
+    /// Inferred from the extension of a file destination (.gz, .zst, .xz or .lz4).
+    /// Sizes and amounts apply to the uncompressed output.
+    #[arg(long, verbatim_doc_comment)]
+    pub compress: Option<Compression>,
+
+    /// Split the output into files of this size.
+    /// Only applies to the ascii, bytes and unicode subcommands.
+    /// Rounded down to whole code units for UTF-16 and UTF-32.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with_all = ["split_lines", "daemon"],
+        verbatim_doc_comment
+    )]
+    pub split_size: Option<SplitSize>,
+
+    /// Split the output into files with this many values each.
+    /// Only applies to the int, float, uuid, url, template and regex subcommands.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with = "daemon",
+        verbatim_doc_comment
+    )]
+    pub split_lines: Option<Size>,
+
+    /// The number of files to split the output into.
+    /// Either splits the size or amount evenly between the files,
+    /// or writes this many files of --split-size or --split-lines each.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with = "daemon",
+        verbatim_doc_comment
+    )]
+    pub files: Option<NonZeroUsize>,
+
+    /// The name of each file when splitting the output.
+    /// {path} is replaced by the destination and {n} by the file number.
+    /// Use {n:<width>} to pad the file number with zeros.
+    /// Default: {path}.{n:4}
+    #[arg(long, verbatim_doc_comment)]
+    pub split_pattern: Option<String>,
+
+    /// Leave holes in a file destination where the output is all zeros.
+    /// Only applies to the ascii, bytes and unicode subcommands with a --size.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with_all = ["preallocate", "daemon"],
+        verbatim_doc_comment
+    )]
+    pub sparse: bool,
+
+    /// Allocate the whole file destination before writing to it.
+    /// Only applies to the ascii, bytes and unicode subcommands with a --size.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with = "daemon",
+        verbatim_doc_comment
+    )]
+    pub preallocate: bool,
+
+    /// Append to a file destination instead of overwriting it.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with_all = ["no_clobber", "atomic", "sparse", "preallocate", "daemon"],
+        verbatim_doc_comment
+    )]
+    pub append: bool,
+
+    /// Refuse to overwrite a file destination which already exists.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with = "daemon",
+        verbatim_doc_comment
+    )]
+    pub no_clobber: bool,
+
+    /// Write to a temporary file next to the file destination
+    /// and only move it into place once everything has been written,
+    /// so a failed or interrupted run never leaves a half-written file behind.
+    #[arg(
+        long,
+        requires("destination"),
+        conflicts_with = "daemon",
+        verbatim_doc_comment
+    )]
+    pub atomic: bool,
 
     #[command(subcommand)]
     pub commands: Command,
@@ -38,6 +160,15 @@ pub enum Command {
         #[arg(short = 'n', long)]
         amount: Option<Size>,
 
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number generated is printed to stderr.
+        #[arg(long, conflicts_with = "amount", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
+        /// The maximum number of integers to generate per second (e.g. 100/s, 10/m or 1/h).
+        #[arg(long)]
+        rate: Option<Rate>,
+
         /// The number of threads to use.
         #[arg(short, long)]
         threads: Option<NonZeroUsize>,
@@ -70,6 +201,15 @@ pub enum Command {
         #[arg(short = 'n', long)]
         amount: Option<Size>,
 
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number generated is printed to stderr.
+        #[arg(long, conflicts_with = "amount", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
+        /// The maximum number of floats to generate per second (e.g. 100/s, 10/m or 1/h).
+        #[arg(long)]
+        rate: Option<Rate>,
+
         /// The number of threads to use.
         #[arg(short, long)]
         threads: Option<NonZeroUsize>,
@@ -92,7 +232,7 @@ pub enum Command {
     #[command(verbatim_doc_comment)]
     Uuid {
         /// The version of the UUID to generate.
-        /// Possible values: empty, v4, max.
+        /// Possible values: empty, v4, v7, max.
         /// Default: v4.
         #[arg(verbatim_doc_comment)]
         version: Option<UuidVersion>,
@@ -102,6 +242,15 @@ pub enum Command {
         #[arg(short = 'n', long)]
         amount: Option<Size>,
 
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number generated is printed to stderr.
+        #[arg(long, conflicts_with = "amount", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
+        /// The maximum number of uuids to generate per second (e.g. 100/s, 10/m or 1/h).
+        #[arg(long)]
+        rate: Option<Rate>,
+
         /// The number of threads to use.
         #[arg(short, long)]
         threads: Option<NonZeroUsize>,
@@ -123,21 +272,71 @@ pub enum Command {
     Url {
         /// The length of the generated strings.
         #[arg(short, long)]
-        length: Option<usize>,
+        length: Option<NonZeroUsize>,
+
+        /// The schemes to pick from (e.g. http,https,ftp,ws,wss,file). Default: https.
+        /// file URLs have no host, e.g. file:///srv/data.
+        #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+        scheme: Option<Vec<Scheme>>,
+
+        /// The top-level domains to pick from (e.g. com,org,co.uk).
+        /// Default: a list of common generic and country code domains.
+        #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+        tld: Option<Vec<String>>,
+
+        /// The number of subdomains, or a range of them (e.g. 0..2). Default: 0.
+        #[arg(long)]
+        subdomains: Option<CountRange>,
 
         /// The number of resource segments.
         #[arg(short = 'r', long)]
         resource: Option<Option<u8>>,
 
-        /// Include a query string.
-        #[arg(short, long)]
-        query: bool,
+        /// Include a query string of key=value pairs.
+        /// Takes the number of pairs or a range of them (e.g. 2..5). Default: 1..3.
+        #[arg(short, long, verbatim_doc_comment)]
+        query: Option<Option<CountRange>>,
+
+        /// The fraction of urls with a port (e.g. 0.25 or 25%).
+        #[arg(long)]
+        port: Option<Ratio>,
+
+        /// The fraction of urls with a user and maybe a password, e.g. user:pass@host.
+        #[arg(long)]
+        userinfo: Option<Ratio>,
+
+        /// The fraction of urls with a #fragment.
+        #[arg(long)]
+        fragment: Option<Ratio>,
+
+        /// The fraction of urls with an IPv4 or IPv6 address instead of a domain.
+        #[arg(long)]
+        ip: Option<Ratio>,
+
+        /// The fraction of domains with non-ASCII characters, which are written in punycode,
+        /// e.g. xn--bcher-kva.de for bücher.de.
+        #[arg(long, verbatim_doc_comment)]
+        idn: Option<Ratio>,
+
+        /// The fraction of path segments and query values with percent-encoded characters,
+        /// e.g. caf%C3%A9%20menu.
+        #[arg(long, verbatim_doc_comment)]
+        percent_encoded: Option<Ratio>,
 
         /// The number of urls to generate.
         /// Each url will be on a new line.
         #[arg(short = 'n', long)]
         amount: Option<Size>,
 
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number generated is printed to stderr.
+        #[arg(long, conflicts_with = "amount", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
+        /// The maximum number of urls to generate per second (e.g. 100/s, 10/m or 1/h).
+        #[arg(long)]
+        rate: Option<Rate>,
+
         /// The number of threads to use.
         #[arg(short, long)]
         threads: Option<NonZeroUsize>,
@@ -154,6 +353,107 @@ pub enum Command {
         progress: bool,
     },
 
+    /// Generate lines from a template with placeholders,
+    /// e.g. '{{uuid}},{{int 18..90}},{{choice "red,green"}},{{ascii 8 alnum}}'.
+    /// Placeholders:
+    ///   {{uuid [version]}}            A UUID. Versions: empty, v4, v7, max. Default: v4.
+    ///   {{int [range]}}               An integer. Default: 0..99.
+    ///   {{float [range]}}             A floating-point number. Default: 0..1.
+    ///   {{ascii [length] [charset]}}  ASCII characters. The length may be a range (e.g. 8..16).
+    ///                                 Uses the same charsets as the ascii subcommand.
+    ///                                 Default: 8 alnum.
+    ///   {{choice "<a>,<b>,..."}}      One of the options, which may also be separate arguments.
+    /// Quote arguments with spaces in double quotes.
+    /// Escape braces and backslashes with \ (e.g. \{{). \n and \t are a newline and a tab.
+    #[command(verbatim_doc_comment)]
+    #[command(group = ArgGroup::new("source").args(["template", "file"]).required(true))]
+    Template {
+        /// The template of each line.
+        template: Option<Template>,
+
+        /// Read the template from a file. A trailing newline is ignored.
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// The number of lines to generate.
+        #[arg(short = 'n', long)]
+        amount: Option<Size>,
+
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number generated is printed to stderr.
+        #[arg(long, conflicts_with = "amount", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
+        /// The maximum number of lines to generate per second (e.g. 100/s, 10/m or 1/h).
+        #[arg(long)]
+        rate: Option<Rate>,
+
+        /// The number of threads to use.
+        #[arg(short, long)]
+        threads: Option<NonZeroUsize>,
+
+        /// The number of lines each thread generates per write.
+        /// The maximum memory allocation will be threads * buf-len.
+        /// Warning: The smaller the buffer length,
+        /// the slower the generation will be due to more frequent writes.
+        #[arg(short, long, verbatim_doc_comment)]
+        buf_size: Option<Size>,
+
+        /// Show a progress bar.
+        #[arg(short, long, default_value = "false")]
+        progress: bool,
+    },
+
+    /// Generate strings which match a regular expression (e.g. '[A-Z]{3}-\d{4}').
+    /// Supports classes, Unicode classes (e.g. \p{Greek}), quantifiers, alternation and groups.
+    /// Strings are checked against anchors and word boundaries,
+    /// so patterns which can't match, like 'a^b', are rejected.
+    /// Classes with printable ASCII characters only generate those (e.g. \d generates 0-9),
+    /// unless --unicode is given.
+    #[command(verbatim_doc_comment)]
+    Regex {
+        /// The regular expression, in the syntax of the regex crate.
+        pattern: Pattern,
+
+        /// The upper bound of quantifiers without one (*, + and {n,}).
+        /// Default: 8.
+        #[arg(long, verbatim_doc_comment)]
+        max_repeat: Option<u32>,
+
+        /// Generate any character a class matches, not only printable ASCII.
+        #[arg(long)]
+        unicode: bool,
+
+        /// The number of strings to generate.
+        /// Each string will be on a new line.
+        #[arg(short = 'n', long)]
+        amount: Option<Size>,
+
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number generated is printed to stderr.
+        #[arg(long, conflicts_with = "amount", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
+        /// The maximum number of strings to generate per second (e.g. 100/s, 10/m or 1/h).
+        #[arg(long)]
+        rate: Option<Rate>,
+
+        /// The number of threads to use.
+        #[arg(short, long)]
+        threads: Option<NonZeroUsize>,
+
+        /// The number of strings each thread generates per write.
+        /// The maximum memory allocation will be threads * buf-len.
+        /// Warning: The smaller the buffer length,
+        /// the slower the generation will be due to more frequent writes.
+        #[arg(short, long, verbatim_doc_comment)]
+        buf_size: Option<Size>,
+
+        /// Show a progress bar.
+        #[arg(short, long, default_value = "false")]
+        progress: bool,
+    },
+
     /// Generate a random ASCII string.
     /// Warning: This command may generate non-printable characters and control characters.
     /// Warning: Your terminal emulator might have trouble rendering large output strings.
@@ -163,31 +463,122 @@ pub enum Command {
     #[command(group=ArgGroup::new("chars").args(&["charset", "printable_only"]).multiple(false))]
     Ascii {
         /// Size of the output. Format: <value><unit>.
-        /// Possible units: B, KB, MB, GB, KiB, MiB, GiB.
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
         #[arg(short, long, verbatim_doc_comment)]
         size: Option<ByteSize>,
 
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number of bytes generated is printed to stderr.
+        #[arg(long, conflicts_with = "size", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
         /// Choose a specific character set.
-        #[arg(short, long)]
-        charset: Option<String>,
+        /// Accepts literal characters, ranges (a-z0-9), POSIX classes ([:upper:])
+        /// and the named sets alpha, lower, upper, digits, alnum, hex, HEX,
+        /// base64, base58, punct and whitespace.
+        /// Combine sets with + (e.g. alnum+_-). Escape +, - and \ with \.
+        #[arg(short, long, verbatim_doc_comment)]
+        charset: Option<Charset>,
 
         /// Only include printable characters.
         #[arg(long)]
         printable_only: bool,
 
         /// Exclude a specific character set.
-        #[arg(short, long)]
-        exclude: Option<String>,
+        /// Uses the same syntax as --charset.
+        #[arg(short, long, verbatim_doc_comment)]
+        exclude: Option<Charset>,
 
         /// Exclude a set of character codes.
         #[arg(long, num_args = 1..)]
         exclude_codes: Option<Vec<u8>>,
 
+        /// The fraction of the output that common compressors can remove (e.g. 0.5 or 50%).
+        /// Mixes random characters with runs of a repeated character.
+        #[arg(long, verbatim_doc_comment)]
+        compressibility: Option<Ratio>,
+
+        /// The fraction of blocks which repeat an earlier block (e.g. 0.3 or 30%),
+        /// for testing deduplicating storage.
+        #[arg(long, verbatim_doc_comment)]
+        dedupe_ratio: Option<Ratio>,
+
+        /// The size of the blocks for --dedupe-ratio. Defaults to 4KiB.
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
+        #[arg(long, requires = "dedupe_ratio", verbatim_doc_comment)]
+        dedupe_block_size: Option<ByteSize>,
+
+        /// How many of the most recent unique blocks a repeated block is copied from.
+        /// Defaults to 1024.
+        #[arg(long, requires = "dedupe_ratio", verbatim_doc_comment)]
+        dedupe_distance: Option<NonZeroUsize>,
+
+        /// The maximum number of bytes to write per second (e.g. 10MB/s).
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
+        #[arg(long, verbatim_doc_comment)]
+        bandwidth: Option<Bandwidth>,
+
+        /// The number of threads to use.
+        #[arg(short, long)]
+        threads: Option<NonZeroUsize>,
+
+        /// The buffer size to use. Divided between threads. Defaults to 1MiB per thread.
+        /// The maximum memory allocation will be threads * buf-size.
+        /// Warning: The smaller the buffer size,
+        /// the slower the generation will be due to more frequent writes.
+        #[arg(short, long, verbatim_doc_comment)]
+        buf_size: Option<ByteSize>,
+
+        /// Show a progress bar.
+        #[arg(short, long, default_value = "false")]
+        progress: bool,
+    },
+
+    /// Generate random bytes.
+    /// Warning: Your terminal emulator might have trouble rendering the output.
+    ///   Consider using the --destination flag to write to a file.
+    #[command(verbatim_doc_comment)]
+    Bytes {
+        /// Size of the output. Format: <value><unit>.
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
+        #[arg(short, long, verbatim_doc_comment)]
+        size: Option<ByteSize>,
+
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number of bytes generated is printed to stderr.
+        #[arg(long, conflicts_with = "size", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
+        /// The fraction of the output that common compressors can remove (e.g. 0.5 or 50%).
+        /// Mixes random bytes with runs of zeros.
+        #[arg(long, verbatim_doc_comment)]
+        compressibility: Option<Ratio>,
+
+        /// The fraction of blocks which repeat an earlier block (e.g. 0.3 or 30%),
+        /// for testing deduplicating storage.
+        #[arg(long, verbatim_doc_comment)]
+        dedupe_ratio: Option<Ratio>,
+
+        /// The size of the blocks for --dedupe-ratio. Defaults to 4KiB.
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
+        #[arg(long, requires = "dedupe_ratio", verbatim_doc_comment)]
+        dedupe_block_size: Option<ByteSize>,
+
+        /// How many of the most recent unique blocks a repeated block is copied from.
+        /// Defaults to 1024.
+        #[arg(long, requires = "dedupe_ratio", verbatim_doc_comment)]
+        dedupe_distance: Option<NonZeroUsize>,
+
+        /// The maximum number of bytes to write per second (e.g. 10MB/s).
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
+        #[arg(long, verbatim_doc_comment)]
+        bandwidth: Option<Bandwidth>,
+
         /// The number of threads to use.
         #[arg(short, long)]
         threads: Option<NonZeroUsize>,
 
-        /// The buffer size to use. Divided between threads.
+        /// The buffer size to use. Divided between threads. Defaults to 1MiB per thread.
         /// The maximum memory allocation will be threads * buf-size.
         /// Warning: The smaller the buffer size,
         /// the slower the generation will be due to more frequent writes.
@@ -208,23 +599,80 @@ pub enum Command {
         encoding: UnicodeEncoding,
 
         /// Size of the output. Format: <value><unit>.
-        /// Possible units: B, KB, MB, GB, KiB, MiB, GiB.
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
         #[arg(short, long, verbatim_doc_comment)]
         size: Option<ByteSize>,
 
+        /// Keep generating until this much time has passed (e.g. 30s, 5m or 1h 30m).
+        /// The total number of bytes generated is printed to stderr.
+        #[arg(long, conflicts_with = "size", verbatim_doc_comment)]
+        duration: Option<humantime::Duration>,
+
         /// Choose a specific character set.
-        #[arg(short, long)]
-        charset: Option<String>,
+        /// Accepts literal characters, ranges (a-z0-9), POSIX classes ([:upper:])
+        /// and the named sets alpha, lower, upper, digits, alnum, hex, HEX,
+        /// base64, base58, punct and whitespace.
+        /// Combine sets with + (e.g. alnum+_-). Escape +, - and \ with \.
+        #[arg(short, long, verbatim_doc_comment)]
+        charset: Option<Charset>,
 
         /// Exclude a specific character set.
-        #[arg(short, long)]
-        exclude: Option<String>,
+        /// Uses the same syntax as --charset.
+        #[arg(short, long, verbatim_doc_comment)]
+        exclude: Option<Charset>,
+
+        /// Only include characters from these scripts.
+        /// Accepts names or abbreviations (e.g. Greek,Han or Grek,Hani).
+        #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+        script: Option<Vec<Script>>,
+
+        /// Only include characters from these blocks
+        /// (e.g. "Basic Latin,CJK Unified Ideographs").
+        #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+        block: Option<Vec<Block>>,
+
+        /// Only include characters from these general categories.
+        /// Accepts categories and groups of categories (e.g. L,Nd,So).
+        #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+        category: Option<Vec<GeneralCategory>>,
+
+        /// Exclude characters from these general categories (e.g. Cc,Cs,Co).
+        #[arg(long, value_delimiter = ',')]
+        exclude_category: Option<Vec<GeneralCategory>>,
+
+        /// Only include characters which are assigned in Unicode 16.0.
+        #[arg(long)]
+        assigned_only: bool,
+
+        /// The byte order of utf16 and utf32 code units.
+        /// Possible values: le, be.
+        /// Default: le.
+        #[arg(long, verbatim_doc_comment)]
+        endian: Option<Endianness>,
+
+        /// Start the output with a byte order mark.
+        /// The byte order mark counts towards the size of the output.
+        #[arg(long, verbatim_doc_comment)]
+        bom: bool,
+
+        /// Replace characters with invalid sequences at the given rate (e.g. 0.01 or 1%).
+        /// utf8: overlong, lone surrogate (WTF-8), truncated and stray continuation bytes.
+        /// utf16: lone surrogates.
+        /// utf32: lone surrogates and values above U+10FFFF.
+        /// A summary of the injected sequences is printed to stderr.
+        #[arg(long, verbatim_doc_comment)]
+        malformed: Option<Ratio>,
+
+        /// The maximum number of bytes to write per second (e.g. 10MB/s).
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
+        #[arg(long, verbatim_doc_comment)]
+        bandwidth: Option<Bandwidth>,
 
         /// The number of threads to use.
         #[arg(short, long)]
         threads: Option<NonZeroUsize>,
 
-        /// The buffer size to use. Divided between threads.
+        /// The buffer size to use. Divided between threads. Defaults to 1MiB per thread.
         /// The maximum memory allocation will be threads * buf-size.
         /// Warning: The smaller the buffer size,
         /// the slower the generation will be due to more frequent writes.
@@ -235,89 +683,492 @@ pub enum Command {
         #[arg(short, long, default_value = "false")]
         progress: bool,
     },
+
+    /// Generate a random directory tree filled with random files.
+    /// Existing files in the directory are left untouched.
+    #[command(verbatim_doc_comment)]
+    Tree {
+        /// The directory to create the tree in.
+        dir: PathBuf,
+
+        /// The number of directory levels below the root directory.
+        /// Default: 3.
+        #[arg(long, verbatim_doc_comment)]
+        depth: Option<usize>,
+
+        /// The number of subdirectories in each directory.
+        /// Either a number or an inclusive range (e.g. 2..5).
+        /// Default: 2..4.
+        #[arg(long, verbatim_doc_comment)]
+        fan_out: Option<CountRange>,
+
+        /// The total number of files, spread randomly over all directories.
+        /// Default: 100.
+        #[arg(short = 'n', long, verbatim_doc_comment)]
+        files: Option<Size>,
+
+        /// The size of each file.
+        /// Either a size or an inclusive range of sizes (e.g. 1KB..1MB).
+        /// Possible units: B, KB, MB, GB, TB, KiB, MiB, GiB, TiB. Fractions like 1.5GiB are allowed.
+        /// Default: 1KB..100KB.
+        #[arg(short, long, verbatim_doc_comment)]
+        size: Option<ByteSizeRange>,
+
+        /// How the file sizes are spread over the range.
+        /// Possible values: uniform, log.
+        /// log picks small files more often, like most real file systems.
+        /// Default: uniform.
+        #[arg(long, verbatim_doc_comment)]
+        distribution: Option<Distribution>,
+
+        /// The characters to use in file and directory names.
+        /// Uses the same syntax as --charset of the ascii subcommand.
+        /// Default: lower+digits.
+        #[arg(long, verbatim_doc_comment)]
+        name_charset: Option<Charset>,
+
+        /// The length of file and directory names.
+        /// Either a number or an inclusive range (e.g. 4..12).
+        /// Default: 4..12.
+        #[arg(long, verbatim_doc_comment)]
+        name_length: Option<CountRange>,
+
+        /// An extension to add to every file name (e.g. txt).
+        #[arg(long)]
+        extension: Option<String>,
+
+        /// What to fill the files with: an ascii, bytes or unicode command
+        /// with any of its options except --size, --duration, --threads and --progress,
+        /// e.g. "bytes --compressibility 0.5" or "unicode utf8 --script Greek".
+        /// Default: "ascii --printable-only".
+        #[arg(long, verbatim_doc_comment)]
+        content: Option<Content>,
+
+        /// The number of threads to use.
+        #[arg(short, long)]
+        threads: Option<NonZeroUsize>,
+
+        /// Show a progress bar.
+        #[arg(short, long, default_value = "false")]
+        progress: bool,
+    },
+
+    /// Serve random data to many clients at once.
+    /// Socket clients send a single request line and receive the generated output.
+    /// A request is either a command line (e.g. int 0..9 -n 100)
+    /// or a JSON object (e.g. {"cmd":"int","range":"0..9","amount":100}).
+    /// HTTP clients request /<command>?<arguments> (e.g. /uuid?version=v7&n=5).
+    #[command(verbatim_doc_comment)]
+    #[command(group = ArgGroup::new("listen").args(["socket", "http"]).required(true).multiple(true))]
+    Serve {
+        /// The path of the Unix domain socket to listen on.
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        /// The address to serve HTTP on (e.g. 127.0.0.1:8080).
+        /// Each generator is an endpoint and the query string holds its arguments
+        /// (e.g. /int?range=0..99&n=10 or /ascii?size=1MiB&charset=alnum).
+        #[arg(long, verbatim_doc_comment)]
+        http: Option<SocketAddr>,
+    },
 }
 
+impl Command {
+    /// The number of values or bytes to generate, if it was given.
+    pub fn total(&self) -> Option<usize> {
+        match self {
+            Command::Int { amount, .. }
+            | Command::Float { amount, .. }
+            | Command::Uuid { amount, .. }
+            | Command::Url { amount, .. }
+            | Command::Template { amount, .. }
+            | Command::Regex { amount, .. } => amount.map(|a| a.get()),
+            Command::Ascii { size, .. }
+            | Command::Bytes { size, .. }
+            | Command::Unicode { size, .. } => size.as_ref().map(ByteSize::to_bytes),
+            Command::Tree { .. } | Command::Serve { .. } => None,
+        }
+    }
+
+    /// The number of threads to generate on, if it was given.
+    pub fn threads(&self) -> Option<usize> {
+        match self {
+            Command::Int { threads, .. }
+            | Command::Float { threads, .. }
+            | Command::Uuid { threads, .. }
+            | Command::Url { threads, .. }
+            | Command::Template { threads, .. }
+            | Command::Regex { threads, .. }
+            | Command::Ascii { threads, .. }
+            | Command::Bytes { threads, .. }
+            | Command::Unicode { threads, .. }
+            | Command::Tree { threads, .. } => threads.map(NonZeroUsize::get),
+            Command::Serve { .. } => None,
+        }
+    }
+
+    /// The number of values or bytes each thread generates per write, if it was given.
+    pub fn buf_size(&self) -> Option<usize> {
+        match self {
+            Command::Int { buf_size, .. }
+            | Command::Float { buf_size, .. }
+            | Command::Uuid { buf_size, .. }
+            | Command::Url { buf_size, .. }
+            | Command::Template { buf_size, .. }
+            | Command::Regex { buf_size, .. } => buf_size.map(|b| b.get()),
+            Command::Ascii { buf_size, .. }
+            | Command::Bytes { buf_size, .. }
+            | Command::Unicode { buf_size, .. } => buf_size.as_ref().map(ByteSize::to_bytes),
+            Command::Tree { .. } | Command::Serve { .. } => None,
+        }
+    }
+
+    /// How long to keep generating for, if it was given.
+    pub fn duration(&self) -> Option<std::time::Duration> {
+        match self {
+            Command::Int { duration, .. }
+            | Command::Float { duration, .. }
+            | Command::Uuid { duration, .. }
+            | Command::Url { duration, .. }
+            | Command::Template { duration, .. }
+            | Command::Regex { duration, .. }
+            | Command::Ascii { duration, .. }
+            | Command::Bytes { duration, .. }
+            | Command::Unicode { duration, .. } => duration.map(Into::into),
+            Command::Tree { .. } | Command::Serve { .. } => None,
+        }
+    }
+
+    /// Whether the output is measured in bytes rather than values.
+    pub fn is_sized(&self) -> bool {
+        matches!(
+            self,
+            Command::Ascii { .. } | Command::Bytes { .. } | Command::Unicode { .. }
+        )
+    }
+
+    /// A copy of the command which generates `total`
+    /// values or bytes on a single thread without a progress bar.
+    pub fn shard(&self, total: usize) -> Command {
+        let mut command = self.clone();
+
+        match &mut command {
+            Command::Int {
+                amount,
+                threads,
+                progress,
+                ..
+            }
+            | Command::Float {
+                amount,
+                threads,
+                progress,
+                ..
+            }
+            | Command::Uuid {
+                amount,
+                threads,
+                progress,
+                ..
+            }
+            | Command::Url {
+                amount,
+                threads,
+                progress,
+                ..
+            }
+            | Command::Template {
+                amount,
+                threads,
+                progress,
+                ..
+            }
+            | Command::Regex {
+                amount,
+                threads,
+                progress,
+                ..
+            } => {
+                *amount = Some(Size { value: total });
+                *threads = NonZeroUsize::new(1);
+                *progress = false;
+            }
+            Command::Ascii {
+                size,
+                threads,
+                progress,
+                ..
+            }
+            | Command::Bytes {
+                size,
+                threads,
+                progress,
+                ..
+            }
+            | Command::Unicode {
+                size,
+                threads,
+                progress,
+                ..
+            } => {
+                *size = Some(ByteSize {
+                    bytes: total,
+                    unit: ByteUnit::B,
+                });
+                *threads = NonZeroUsize::new(1);
+                *progress = false;
+            }
+            Command::Tree { .. } | Command::Serve { .. } => {}
+        }
+
+        command
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Parser)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+    Lz4,
+}
+
+impl Compression {
+    /// The usual file name extension of the format.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+            Compression::Xz => Some("xz"),
+            Compression::Lz4 => Some("lz4"),
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "xz" => Some(Compression::Xz),
+            "lz4" => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            _ => Compression::from_extension(s).ok_or_else(|| {
+                format!("Unknown compression {s}, expected none, gzip, zstd, xz or lz4")
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Destination {
+    File(PathBuf),
+    Tcp(String),
+    TcpListen(String),
+    Udp(String),
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let network = [
+            ("tcp://", Destination::Tcp as fn(String) -> Destination),
+            ("tcp-listen://", Destination::TcpListen),
+            ("udp://", Destination::Udp),
+        ];
+
+        for (scheme, destination) in network {
+            if let Some(addr) = s.strip_prefix(scheme) {
+                // Must be <host>:<port>
+                return match addr.rsplit_once(':') {
+                    Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                        Ok(destination(addr.to_owned()))
+                    }
+                    _ => Err(format!(
+                        "Expected {scheme}<host>:<port> with a port from 0 to 65535, e.g. {scheme}localhost:9000"
+                    )),
+                };
+            }
+        }
+
+        Ok(Destination::File(PathBuf::from(s)))
+    }
+}
+
+/// An inclusive range of integers. Format: <min>..<max>.
 #[derive(Debug, Clone, Parser)]
 pub struct IntRange {
     pub min: i64,
     pub max: i64,
 }
 
+const INT_RANGE_SYNTAX: &str =
+    "Ranges are <min>..<max> with both ends included, e.g. 0..100 or -50..50";
+
 impl FromStr for IntRange {
-    type Err = Error;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split("..").collect();
+        let Some((min, max)) = s.split_once("..") else {
+            return Err(missing_dots(s, INT_RANGE_SYNTAX));
+        };
 
-        if parts.len() != 2 {
-            return Err(Error::new(ErrorKind::ValueValidation));
-        }
+        let min = parse_int(min, "start")?;
+        let max = parse_int(max, "end")?;
 
-        let min = parts[0]
-            .parse()
-            .map_err(|_| Error::new(clap::error::ErrorKind::ValueValidation))?;
-        let max = parts[1]
-            .parse()
-            .map_err(|_| Error::new(clap::error::ErrorKind::ValueValidation))?;
+        if min > max {
+            return Err(format!(
+                "The range is empty because {min} is greater than {max}, did you mean {max}..{min}?"
+            ));
+        }
 
         Ok(IntRange { min, max })
     }
 }
 
+fn parse_int(s: &str, end: &str) -> Result<i64, String> {
+    let s = s.trim();
+
+    match s.parse::<i64>() {
+        Ok(value) => Ok(value),
+        Err(_) if s.is_empty() => Err(format!(
+            "The {end} of the range is missing. {INT_RANGE_SYNTAX}"
+        )),
+        Err(_) if s.parse::<f64>().is_ok_and(f64::is_finite) => {
+            if s.trim_start_matches('-')
+                .chars()
+                .all(|c| c.is_ascii_digit())
+            {
+                Err(format!(
+                    "{s} is too large, integers must be between {} and {}",
+                    i64::MIN,
+                    i64::MAX
+                ))
+            } else {
+                Err(format!(
+                    "{s} isn't a whole number, use the float subcommand for decimals"
+                ))
+            }
+        }
+        Err(_) => Err(format!("{s} isn't a number. {INT_RANGE_SYNTAX}")),
+    }
+}
+
+/// An inclusive range of floating-point numbers. Format: <min>..<max>.
 #[derive(Debug, Clone, Parser)]
 pub struct FloatRange {
     pub min: f64,
     pub max: f64,
 }
 
+const FLOAT_RANGE_SYNTAX: &str =
+    "Ranges are <min>..<max> with both ends included, e.g. 0..1 or -2.5..2.5";
+
 impl FromStr for FloatRange {
-    type Err = Error;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split("..").collect();
+        let Some((min, max)) = s.split_once("..") else {
+            return Err(missing_dots(s, FLOAT_RANGE_SYNTAX));
+        };
 
-        if parts.len() != 2 {
-            return Err(Error::new(clap::error::ErrorKind::ValueValidation));
-        }
+        let min = parse_float(min, "start")?;
+        let max = parse_float(max, "end")?;
 
-        let min = parts[0]
-            .parse()
-            .map_err(|_| Error::new(clap::error::ErrorKind::ValueValidation))?;
-        let max = parts[1]
-            .parse()
-            .map_err(|_| Error::new(clap::error::ErrorKind::ValueValidation))?;
+        if min > max {
+            return Err(format!(
+                "The range is empty because {min} is greater than {max}, did you mean {max}..{min}?"
+            ));
+        }
 
         Ok(FloatRange { min, max })
     }
 }
 
+fn parse_float(s: &str, end: &str) -> Result<f64, String> {
+    let s = s.trim();
+
+    match s.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        Ok(_) => Err(format!("The {end} of the range must be a finite number")),
+        Err(_) if s.is_empty() => Err(format!(
+            "The {end} of the range is missing. {FLOAT_RANGE_SYNTAX}"
+        )),
+        Err(_) => Err(format!("{s} isn't a number. {FLOAT_RANGE_SYNTAX}")),
+    }
+}
+
+/// Explains a range without "..", guessing what it was meant to be.
+fn missing_dots(s: &str, syntax: &str) -> String {
+    let s = s.trim();
+
+    // A dash or a single dot between the ends, like 0-100 or 0.100.
+    // A leading minus belongs to the start.
+    let separator = s
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '-' || *c == ':' || *c == ',')
+        .map(|(i, _)| i);
+
+    if let Some(i) = separator {
+        let (min, max) = (s[..i].trim(), s[i + 1..].trim());
+
+        if !min.is_empty() && !max.is_empty() {
+            return format!("Expected .. between the ends, did you mean {min}..{max}? {syntax}");
+        }
+    }
+
+    if !s.is_empty() && s.parse::<f64>().is_ok() {
+        return format!("Expected a range, did you mean 0..{s}? {syntax}");
+    }
+
+    format!("Expected a range. {syntax}")
+}
+
 #[derive(Debug, Clone, Parser)]
 pub enum UuidVersion {
     Empty,
     V4,
+    V7,
     Max,
 }
 
 impl FromStr for UuidVersion {
-    type Err = Error;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "empty" => Ok(UuidVersion::Empty),
             "4" | "v4" | "ver4" | "version4" => Ok(UuidVersion::V4),
+            "7" | "v7" | "ver7" | "version7" => Ok(UuidVersion::V7),
             "max" => Ok(UuidVersion::Max),
-            _ => Err(Error::new(clap::error::ErrorKind::ValueValidation)),
+            _ => Err(format!(
+                "Unknown uuid version {s}, expected empty, v4, v7 or max"
+            )),
         }
     }
 }
 
+/// A number of bytes, written with a unit.
 #[derive(Debug, Clone, Parser)]
 pub struct ByteSize {
-    pub value: usize,
+    pub bytes: usize,
+    /// The unit it was written in, which decides how progress is shown.
     pub unit: ByteUnit,
 }
 
-#[derive(Debug, Clone, Parser)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Parser)]
 pub enum ByteUnit {
     B,
     KB,
@@ -326,25 +1177,22 @@ pub enum ByteUnit {
     MiB,
     GB,
     GiB,
+    TB,
+    TiB,
 }
 
+const SIZE_SYNTAX: &str =
+    "Sizes are a number and a unit (B, KB, KiB, MB, MiB, GB, GiB, TB or TiB), e.g. 512B, 1.5GiB or 10 MB";
+
 impl ByteSize {
     pub fn to_bytes(&self) -> usize {
-        match self.unit {
-            ByteUnit::B => self.value,
-            ByteUnit::KB => self.value * 1000,
-            ByteUnit::KiB => self.value * 1024,
-            ByteUnit::MB => self.value * 1000 * 1000,
-            ByteUnit::MiB => self.value * 1024 * 1024,
-            ByteUnit::GB => self.value * 1000 * 1000 * 1000,
-            ByteUnit::GiB => self.value * 1024 * 1024 * 1024,
-        }
+        self.bytes
     }
 
     pub fn is_decimal_unit(&self) -> bool {
         match self.unit {
-            ByteUnit::KB | ByteUnit::MB | ByteUnit::GB => true,
-            ByteUnit::B | ByteUnit::KiB | ByteUnit::MiB | ByteUnit::GiB => false,
+            ByteUnit::KB | ByteUnit::MB | ByteUnit::GB | ByteUnit::TB => true,
+            ByteUnit::B | ByteUnit::KiB | ByteUnit::MiB | ByteUnit::GiB | ByteUnit::TiB => false,
         }
     }
 
@@ -354,110 +1202,397 @@ impl ByteSize {
 }
 
 impl FromStr for ByteSize {
-    type Err = Error;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut string_value = String::new();
+        let (number, suffix) = split_unit(s);
 
-        for c in s.chars() {
-            if c.is_ascii_digit() {
-                string_value.push(c);
-            } else {
-                break;
+        if number.is_empty() {
+            return Err(format!("Expected a number. {SIZE_SYNTAX}"));
+        }
+
+        let Some(unit) = ByteUnit::from_suffix(suffix) else {
+            return Err(unknown_byte_unit(number, suffix));
+        };
+
+        let bytes = scale(number, unit.multiplier()).map_err(|e| match e {
+            ScaleError::NotANumber => format!("{number} isn't a number. {SIZE_SYNTAX}"),
+            ScaleError::NotWhole(nearest) => {
+                format!(
+                    "{} isn't a whole number of bytes, did you mean {nearest}B?",
+                    s.trim()
+                )
             }
+            ScaleError::TooLarge => format!("{} is too large", s.trim()),
+        })?;
+
+        Ok(ByteSize { bytes, unit })
+    }
+}
+
+/// The size of each file when splitting the output, at least 1B.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitSize {
+    pub bytes: usize,
+}
+
+impl FromStr for SplitSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.parse::<ByteSize>()?.to_bytes();
+
+        if bytes == 0 {
+            return Err("The split size must be greater than 0B".to_owned());
         }
 
-        Ok(ByteSize {
-            value: string_value
-                .parse()
-                .map_err(|_| Error::new(clap::error::ErrorKind::ValueValidation))?,
-            unit: s[string_value.len()..].parse()?,
-        })
+        Ok(SplitSize { bytes })
+    }
+}
+
+/// The largest payload of a UDP datagram over IPv4.
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// The payload size of UDP datagrams, from 1B up to 65507B.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatagramSize {
+    pub bytes: usize,
+}
+
+impl FromStr for DatagramSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.parse::<ByteSize>()?.to_bytes();
+
+        if bytes == 0 {
+            return Err("Datagrams must have at least 1B of payload".to_owned());
+        }
+
+        if bytes > MAX_DATAGRAM_SIZE {
+            return Err(format!(
+                "{} is larger than the largest UDP payload of {MAX_DATAGRAM_SIZE}B",
+                s.trim()
+            ));
+        }
+
+        Ok(DatagramSize { bytes })
     }
 }
 
 impl FromStr for ByteUnit {
-    type Err = Error;
+    type Err = String;
+
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "B" | "b" => Ok(ByteUnit::B),
-            "kb" | "kB" | "KB" => Ok(ByteUnit::KB),
-            "kib" | "KiB" => Ok(ByteUnit::KiB),
-            "mb" | "mB" | "MB" => Ok(ByteUnit::MB),
-            "mib" | "MiB" => Ok(ByteUnit::MiB),
-            "gb" | "gB" | "GB" => Ok(ByteUnit::GB),
-            "gib" | "GiB" => Ok(ByteUnit::GiB),
-            _ => Err(Error::new(clap::error::ErrorKind::ValueValidation)),
+        ByteUnit::from_suffix(s.trim()).ok_or_else(|| format!("Unknown unit {s}. {SIZE_SYNTAX}"))
+    }
+}
+
+impl ByteUnit {
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix.to_ascii_lowercase().as_str() {
+            "b" => Some(ByteUnit::B),
+            "kb" => Some(ByteUnit::KB),
+            "kib" => Some(ByteUnit::KiB),
+            "mb" => Some(ByteUnit::MB),
+            "mib" => Some(ByteUnit::MiB),
+            "gb" => Some(ByteUnit::GB),
+            "gib" => Some(ByteUnit::GiB),
+            "tb" => Some(ByteUnit::TB),
+            "tib" => Some(ByteUnit::TiB),
+            _ => None,
         }
     }
+
+    fn multiplier(self) -> u64 {
+        match self {
+            ByteUnit::B => 1,
+            ByteUnit::KB => 1000,
+            ByteUnit::KiB => 1 << 10,
+            ByteUnit::MB => 1000 * 1000,
+            ByteUnit::MiB => 1 << 20,
+            ByteUnit::GB => 1000 * 1000 * 1000,
+            ByteUnit::GiB => 1 << 30,
+            ByteUnit::TB => 1000 * 1000 * 1000 * 1000,
+            ByteUnit::TiB => 1 << 40,
+        }
+    }
+}
+
+/// Suggests the units the suffix was probably meant to be.
+fn unknown_byte_unit(number: &str, suffix: &str) -> String {
+    if suffix.is_empty() {
+        return format!("The unit is missing, did you mean {number}B? {SIZE_SYNTAX}");
+    }
+
+    let prefix = match suffix.chars().next().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => "K",
+        Some('m') => "M",
+        Some('g') => "G",
+        Some('t') => "T",
+        _ => return format!("Unknown unit {suffix}. {SIZE_SYNTAX}"),
+    };
+
+    format!(
+        "Unknown unit {suffix}, did you mean {number}{prefix}B or {number}{prefix}iB? {SIZE_SYNTAX}"
+    )
 }
 
+/// A number of values, with an optional K (thousand), M (million), B (billion) or T (trillion).
 #[derive(Debug, Clone, Copy, Parser)]
 pub struct Size {
     pub value: usize,
-    pub unit: Unit,
 }
 
-#[derive(Debug, Clone, Copy, Parser)]
-pub enum Unit {
-    None,
-    K,
-    M,
-    B,
-    T,
-}
+const AMOUNT_SYNTAX: &str = "Amounts are a number with an optional K (thousand), M (million), B (billion) or T (trillion), e.g. 500, 1.5M or 2B";
 
 impl Size {
     pub fn get(&self) -> usize {
-        match self.unit {
-            Unit::None => self.value,
-            Unit::K => self.value * 1000,
-            Unit::M => self.value * 1000 * 1000,
-            Unit::B => self.value * 1000 * 1000 * 1000,
-            Unit::T => self.value * 1000 * 1000 * 1000 * 1000,
-        }
+        self.value
     }
 }
 
 impl FromStr for Size {
-    type Err = Error;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut string_value = String::new();
+        let (number, suffix) = split_unit(s);
 
-        for c in s.chars() {
-            if c.is_ascii_digit() {
-                string_value.push(c);
-            } else {
-                break;
-            }
+        if number.is_empty() {
+            return Err(format!("Expected a number. {AMOUNT_SYNTAX}"));
         }
 
-        let value = string_value
-            .parse()
-            .map_err(|_| Error::new(clap::error::ErrorKind::ValueValidation))?;
+        let multiplier = match suffix.to_ascii_lowercase().as_str() {
+            "" => 1,
+            "k" => 1000,
+            "m" => 1000 * 1000,
+            "b" => 1000 * 1000 * 1000,
+            "t" => 1000 * 1000 * 1000 * 1000,
+            // Easily mixed up with sizes
+            unit @ ("kb" | "mb" | "gb" | "tb" | "kib" | "mib" | "gib" | "tib") => {
+                let suggestion = match &unit[..1] {
+                    "g" => "B".to_owned(),
+                    prefix => prefix.to_ascii_uppercase(),
+                };
+
+                return Err(format!(
+                    "Amounts count values, not bytes, did you mean {number}{suggestion}? {AMOUNT_SYNTAX}"
+                ));
+            }
+            _ => return Err(format!("Unknown suffix {suffix}. {AMOUNT_SYNTAX}")),
+        };
+
+        let value = scale(number, multiplier).map_err(|e| match e {
+            ScaleError::NotANumber => format!("{number} isn't a number. {AMOUNT_SYNTAX}"),
+            ScaleError::NotWhole(nearest) => {
+                format!("{} isn't a whole number, did you mean {nearest}?", s.trim())
+            }
+            ScaleError::TooLarge => format!("{} is too large", s.trim()),
+        })?;
 
         if value == 0 {
-            return Err(Error::new(clap::error::ErrorKind::InvalidValue));
+            return Err("The amount must be greater than 0".to_owned());
         }
 
-        Ok(Size {
-            value,
-            unit: s[string_value.len()..].parse()?,
-        })
+        Ok(Size { value })
+    }
+}
+
+/// Splits a number from its unit, e.g. " 1.5 GiB" into "1.5" and "GiB".
+fn split_unit(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    let end = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+
+    (&s[..end], s[end..].trim())
+}
+
+enum ScaleError {
+    NotANumber,
+    /// The result has a fraction, with the nearest whole number.
+    NotWhole(u128),
+    TooLarge,
+}
+
+/// Multiplies a decimal number like 1.5 without the rounding errors of floats.
+fn scale(number: &str, multiplier: u64) -> Result<usize, ScaleError> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+
+    if whole.is_empty() && fraction.is_empty() || fraction.contains('.') {
+        return Err(ScaleError::NotANumber);
+    }
+
+    let digits: u128 = format!("{whole}{fraction}")
+        .parse()
+        .map_err(|_| ScaleError::TooLarge)?;
+
+    let denominator = 10_u128
+        .checked_pow(fraction.len() as u32)
+        .ok_or(ScaleError::TooLarge)?;
+
+    let scaled = digits
+        .checked_mul(multiplier as u128)
+        .ok_or(ScaleError::TooLarge)?;
+
+    if scaled % denominator != 0 {
+        return Err(ScaleError::NotWhole(
+            (scaled + denominator / 2) / denominator,
+        ));
+    }
+
+    usize::try_from(scaled / denominator).map_err(|_| ScaleError::TooLarge)
+}
+
+/// An inclusive range of counts. A single number is a range of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Parser)]
+pub struct CountRange {
+    pub min: usize,
+    pub max: usize,
+}
+
+const COUNT_RANGE_SYNTAX: &str =
+    "Counts are a whole number or a range <min>..<max> with both ends included, e.g. 8 or 4..16";
+
+impl FromStr for CountRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| {
+            let s = s.trim();
+
+            s.parse::<usize>()
+                .map_err(|_| format!("{s} isn't a whole number. {COUNT_RANGE_SYNTAX}"))
+        };
+
+        let (min, max) = match s.split_once("..") {
+            Some((min, max)) => (parse(min)?, parse(max)?),
+            None => (parse(s)?, parse(s)?),
+        };
+
+        if min > max {
+            return Err(format!(
+                "The range is empty because {min} is greater than {max}, did you mean {max}..{min}?"
+            ));
+        }
+
+        Ok(CountRange { min, max })
+    }
+}
+
+/// An inclusive range of sizes in bytes. A single size is a range of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Parser)]
+pub struct ByteSizeRange {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl FromStr for ByteSizeRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = match s.split_once("..") {
+            Some((min, max)) => (min.parse::<ByteSize>()?, max.parse::<ByteSize>()?),
+            None => (s.parse::<ByteSize>()?, s.parse::<ByteSize>()?),
+        };
+
+        let (min, max) = (min.to_bytes(), max.to_bytes());
+
+        if min > max {
+            return Err(format!(
+                "The range is empty because {min}B is greater than {max}B"
+            ));
+        }
+
+        Ok(ByteSizeRange { min, max })
     }
 }
 
-impl FromStr for Unit {
-    type Err = Error;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Parser)]
+pub enum Distribution {
+    Uniform,
+    Log,
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "" => Ok(Unit::None),
-            "k" | "K" => Ok(Unit::K),
-            "m" | "M" => Ok(Unit::M),
-            "b" | "B" => Ok(Unit::B),
-            "t" | "T" => Ok(Unit::T),
-            _ => Err(Error::new(clap::error::ErrorKind::ValueValidation)),
+            "uniform" => Ok(Distribution::Uniform),
+            "log" | "log-uniform" => Ok(Distribution::Log),
+            _ => Err(format!("Unknown distribution {s}, expected uniform or log")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Parser)]
+pub enum Scheme {
+    Http,
+    Https,
+    Ftp,
+    Ws,
+    Wss,
+    File,
+}
+
+impl FromStr for Scheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(Scheme::Http),
+            "https" => Ok(Scheme::Https),
+            "ftp" => Ok(Scheme::Ftp),
+            "ws" => Ok(Scheme::Ws),
+            "wss" => Ok(Scheme::Wss),
+            "file" => Ok(Scheme::File),
+            _ => Err(format!(
+                "Unknown scheme {s}, expected http, https, ftp, ws, wss or file"
+            )),
+        }
+    }
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+            Scheme::Ftp => "ftp",
+            Scheme::Ws => "ws",
+            Scheme::Wss => "wss",
+            Scheme::File => "file",
+        }
+    }
+}
+
+/// The ascii, bytes or unicode command which fills the files of a tree,
+/// written like on the command line, e.g. `unicode utf8 --script Greek`.
+#[derive(Debug, Clone)]
+pub struct Content(pub Box<Command>);
+
+impl FromStr for Content {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = crate::server::split_command_line(s)?;
+
+        let args = GenArgs::try_parse_from(std::iter::once("gen".to_owned()).chain(words))
+            .map_err(|e| {
+                // Only the reason, since the usage would be that of the whole program
+                let message = e.to_string();
+                let reason = message.lines().next().unwrap_or_default();
+                reason.trim_start_matches("error: ").to_owned()
+            })?;
+
+        match args.commands {
+            command @ (Command::Ascii { .. } | Command::Bytes { .. } | Command::Unicode { .. }) => {
+                Ok(Content(Box::new(command)))
+            }
+            _ => Err(format!(
+                "Unknown content {s}, expected an ascii, bytes or unicode command, e.g. \"ascii --charset alnum\""
+            )),
         }
     }
 }
@@ -470,14 +1605,36 @@ pub enum UnicodeEncoding {
 }
 
 impl FromStr for UnicodeEncoding {
-    type Err = Error;
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "utf_8" | "utf-8" | "utf8" | "8" => Ok(UnicodeEncoding::Utf8),
-            "utf_16" | "utf-16" | "utf16" | "16" => Ok(UnicodeEncoding::Utf16),
-            "utf_32" | "utf-32" | "utf32" | "32" => Ok(UnicodeEncoding::Utf32),
-            _ => Err(Error::new(clap::error::ErrorKind::ValueValidation)),
+        match s
+            .trim()
+            .to_ascii_lowercase()
+            .replace(['-', '_'], "")
+            .as_str()
+        {
+            "utf8" | "8" => Ok(UnicodeEncoding::Utf8),
+            "utf16" | "16" => Ok(UnicodeEncoding::Utf16),
+            "utf32" | "32" => Ok(UnicodeEncoding::Utf32),
+            "ucs2" => Err("UCS-2 is a subset of UTF-16, use utf16".to_owned()),
+            "ucs4" => Err("UCS-4 is the same as UTF-32, use utf32".to_owned()),
+            "ascii" => Err("Use the ascii subcommand for ASCII".to_owned()),
+            _ => Err(format!(
+                "Unknown encoding {s}, expected utf8, utf16 or utf32"
+            )),
+        }
+    }
+}
+
+impl UnicodeEncoding {
+    pub fn bom(&self, endian: Endianness) -> Vec<u8> {
+        match (self, endian) {
+            (UnicodeEncoding::Utf8, _) => vec![0xEF, 0xBB, 0xBF],
+            (UnicodeEncoding::Utf16, Endianness::Little) => vec![0xFF, 0xFE],
+            (UnicodeEncoding::Utf16, Endianness::Big) => vec![0xFE, 0xFF],
+            (UnicodeEncoding::Utf32, Endianness::Little) => vec![0xFF, 0xFE, 0x00, 0x00],
+            (UnicodeEncoding::Utf32, Endianness::Big) => vec![0x00, 0x00, 0xFE, 0xFF],
         }
     }
 }
@@ -491,3 +1648,286 @@ impl Display for UnicodeEncoding {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Parser)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl FromStr for Endianness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "le" | "LE" | "little" => Ok(Endianness::Little),
+            "be" | "BE" | "big" => Ok(Endianness::Big),
+            _ => Err(format!(
+                "Unknown byte order {s}, expected le (little) or be (big)"
+            )),
+        }
+    }
+}
+
+/// A fraction between 0 and 1. Either a decimal (e.g. 0.25) or a percentage (e.g. 25%).
+#[derive(Debug, Clone, Copy, PartialEq, Parser)]
+pub struct Ratio {
+    pub value: f64,
+}
+
+const RATIO_SYNTAX: &str = "Ratios are a decimal or a percentage, e.g. 0.25 or 25%";
+
+impl FromStr for Ratio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let not_a_number = || format!("{s} isn't a number. {RATIO_SYNTAX}");
+
+        let value: f64 = match s.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().map_err(|_| not_a_number())? / 100.0,
+            None => s.parse().map_err(|_| not_a_number())?,
+        };
+
+        if !(0.0..=1.0).contains(&value) {
+            return Err(format!(
+                "{s} is out of range, ratios must be between 0 and 1 (0% and 100%)"
+            ));
+        }
+
+        Ok(Ratio { value })
+    }
+}
+
+/// A number of values per second. Format: <value>[/s|/m|/h].
+#[derive(Debug, Clone, Copy, PartialEq, Parser)]
+pub struct Rate {
+    pub per_second: f64,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, seconds) = split_time_unit(s)?;
+
+        let value: f64 = value.trim().parse().map_err(|_| {
+            format!(
+                "{} isn't a number, rates look like 100, 1000/s or 2.5/m",
+                value.trim()
+            )
+        })?;
+
+        let per_second = value / seconds;
+
+        if !per_second.is_finite() || per_second <= 0.0 {
+            return Err("The rate must be greater than 0".to_owned());
+        }
+
+        Ok(Rate { per_second })
+    }
+}
+
+/// A number of bytes per second. Format: <value><unit>[/s|/m|/h].
+#[derive(Debug, Clone, Copy, PartialEq, Parser)]
+pub struct Bandwidth {
+    pub bytes_per_second: f64,
+}
+
+impl FromStr for Bandwidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, seconds) = split_time_unit(s)?;
+
+        let bytes = value.parse::<ByteSize>()?.to_bytes();
+
+        if bytes == 0 {
+            return Err("The bandwidth must be greater than 0".to_owned());
+        }
+
+        Ok(Bandwidth {
+            bytes_per_second: bytes as f64 / seconds,
+        })
+    }
+}
+
+/// Splits the time unit off a rate and returns the number of seconds it stands for.
+/// A rate without a time unit is per second.
+fn split_time_unit(s: &str) -> Result<(&str, f64), String> {
+    match s.rsplit_once('/') {
+        None => Ok((s, 1.0)),
+        Some((value, "s")) => Ok((value, 1.0)),
+        Some((value, "m")) => Ok((value, 60.0)),
+        Some((value, "h")) => Ok((value, 60.0 * 60.0)),
+        Some((_, unit)) => Err(format!(
+            "Unknown time unit /{unit}, expected /s, /m or /h, e.g. 10MB/s"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ByteSize, ByteSizeRange, ByteUnit, Compression, Content, CountRange, DatagramSize,
+        Destination, Distribution, Endianness, FloatRange, IntRange, Ratio, Scheme, Size,
+        SplitSize, UuidVersion,
+    };
+
+    #[test]
+    fn int_ranges() {
+        let range = "-50..50".parse::<IntRange>().unwrap();
+        assert_eq!((range.min, range.max), (-50, 50));
+
+        let range = " 7 .. 7 ".parse::<IntRange>().unwrap();
+        assert_eq!((range.min, range.max), (7, 7));
+
+        let error = |s: &str| s.parse::<IntRange>().unwrap_err();
+        assert!(error("0-100").contains("did you mean 0..100?"));
+        assert!(error("-5:5").contains("did you mean -5..5?"));
+        assert!(error("100").contains("did you mean 0..100?"));
+        assert!(error("10..1").contains("did you mean 1..10?"));
+        assert!(error("..5").starts_with("The start of the range is missing"));
+        assert!(error("0..1.5").contains("isn't a whole number"));
+        assert!(error("0..99999999999999999999").contains("is too large"));
+        assert!(error("a..b").starts_with("a isn't a number"));
+    }
+
+    #[test]
+    fn float_ranges() {
+        let range = "-2.5..2.5".parse::<FloatRange>().unwrap();
+        assert_eq!((range.min, range.max), (-2.5, 2.5));
+
+        let error = |s: &str| s.parse::<FloatRange>().unwrap_err();
+        assert!(error("1..0").contains("did you mean 0..1?"));
+        assert!(error("0..inf").contains("must be a finite number"));
+        assert!(error("0..").starts_with("The end of the range is missing"));
+    }
+
+    #[test]
+    fn byte_sizes() {
+        let bytes = |s: &str| s.parse::<ByteSize>().unwrap().to_bytes();
+
+        assert_eq!(bytes("512B"), 512);
+        assert_eq!(bytes("10 MB"), 10_000_000);
+        assert_eq!(bytes("1.5GiB"), 3 << 29);
+        assert_eq!(bytes("0.5kb"), 500);
+        assert_eq!(bytes("2TB"), 2_000_000_000_000);
+        assert_eq!(bytes("1TiB"), 1 << 40);
+
+        let size = "1.5GiB".parse::<ByteSize>().unwrap();
+        assert_eq!(size.unit, ByteUnit::GiB);
+        assert!(size.is_binary_unit());
+        assert!("1GB".parse::<ByteSize>().unwrap().is_decimal_unit());
+
+        let error = |s: &str| s.parse::<ByteSize>().unwrap_err();
+        assert!(error("10M").contains("did you mean 10MB or 10MiB?"));
+        assert!(error("100").contains("The unit is missing, did you mean 100B?"));
+        assert!(error("1.5B").contains("did you mean 2B?"));
+        assert!(error("1.2.3MB").starts_with("1.2.3 isn't a number"));
+        assert!(error("GB").starts_with("Expected a number"));
+        assert!(error("10XB").starts_with("Unknown unit XB"));
+        assert!(error("99999999999TiB").ends_with("is too large"));
+    }
+
+    #[test]
+    fn byte_size_ranges() {
+        let range = "1KB..1MiB".parse::<ByteSizeRange>().unwrap();
+        assert_eq!((range.min, range.max), (1000, 1 << 20));
+
+        let range = "4KiB".parse::<ByteSizeRange>().unwrap();
+        assert_eq!((range.min, range.max), (4096, 4096));
+
+        assert!("1MB..1KB"
+            .parse::<ByteSizeRange>()
+            .unwrap_err()
+            .contains("1000000B is greater than 1000B"));
+    }
+
+    #[test]
+    fn amounts() {
+        let value = |s: &str| s.parse::<Size>().unwrap().get();
+
+        assert_eq!(value("500"), 500);
+        assert_eq!(value("1.5M"), 1_500_000);
+        assert_eq!(value("2B"), 2_000_000_000);
+        assert_eq!(value("1t"), 1_000_000_000_000);
+
+        let error = |s: &str| s.parse::<Size>().unwrap_err();
+        assert!(error("10MB").contains("Amounts count values, not bytes, did you mean 10M?"));
+        assert!(error("1GiB").contains("did you mean 1B?"));
+        assert!(error("1.0001K").contains("did you mean 1000?"));
+        assert!(error("0").contains("must be greater than 0"));
+        assert!(error("5X").starts_with("Unknown suffix X"));
+    }
+
+    #[test]
+    fn value_errors() {
+        assert!("4..x"
+            .parse::<CountRange>()
+            .unwrap_err()
+            .starts_with("x isn't a whole number"));
+        assert!("9..3"
+            .parse::<CountRange>()
+            .unwrap_err()
+            .contains("did you mean 3..9?"));
+        assert!("150%"
+            .parse::<Ratio>()
+            .unwrap_err()
+            .contains("between 0 and 1"));
+        assert!("half"
+            .parse::<Ratio>()
+            .unwrap_err()
+            .starts_with("half isn't a number"));
+        assert!("tcp://localhost"
+            .parse::<Destination>()
+            .unwrap_err()
+            .starts_with("Expected tcp://<host>:<port>"));
+        assert!("brotli"
+            .parse::<Compression>()
+            .unwrap_err()
+            .starts_with("Unknown compression brotli"));
+        assert!("v9"
+            .parse::<UuidVersion>()
+            .unwrap_err()
+            .contains("expected empty, v4"));
+        assert!("normal"
+            .parse::<Distribution>()
+            .unwrap_err()
+            .contains("uniform or log"));
+        assert!("gopher"
+            .parse::<Scheme>()
+            .unwrap_err()
+            .contains("expected http"));
+        assert!("int"
+            .parse::<Content>()
+            .unwrap_err()
+            .contains("expected an ascii, bytes or unicode command"));
+        assert!("ascii --foo"
+            .parse::<Content>()
+            .unwrap_err()
+            .starts_with("unexpected argument '--foo'"));
+        assert!("middle"
+            .parse::<Endianness>()
+            .unwrap_err()
+            .contains("le (little)"));
+    }
+
+    #[test]
+    fn datagram_size_fits_udp() {
+        assert_eq!("1472B".parse::<DatagramSize>().unwrap().bytes, 1472);
+        assert_eq!("65507B".parse::<DatagramSize>().unwrap().bytes, 65507);
+        assert!("65508B".parse::<DatagramSize>().is_err());
+        assert!("64KiB".parse::<DatagramSize>().is_err());
+        assert!("0B".parse::<DatagramSize>().is_err());
+    }
+
+    #[test]
+    fn split_sizes() {
+        assert_eq!("1KiB".parse::<SplitSize>().unwrap().bytes, 1024);
+        assert!("0B"
+            .parse::<SplitSize>()
+            .unwrap_err()
+            .contains("must be greater than 0B"));
+    }
+}
@@ -0,0 +1,248 @@
+use std::{collections::HashSet, str::FromStr};
+
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const PUNCT: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+const WHITESPACE: &str = " \t\n\r\x0b\x0c";
+const BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// A set of characters parsed from a charset expression.
+///
+/// An expression is made of parts joined with `+`.
+/// Each part is either a named set (e.g. `alnum`),
+/// or a sequence of literal characters, ranges (`a-z`) and POSIX classes (`[:upper:]`).
+/// Use `\` to escape `+`, `-` and `\` itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Charset {
+    /// In the order they were given.
+    chars: Vec<char>,
+    /// The same characters, to look them up without scanning large ranges.
+    set: HashSet<char>,
+}
+
+impl Charset {
+    pub fn contains(&self, c: char) -> bool {
+        self.set.contains(&c)
+    }
+
+    pub fn into_chars(self) -> Vec<char> {
+        self.chars
+    }
+
+    fn push(&mut self, c: char) {
+        if self.set.insert(c) {
+            self.chars.push(c);
+        }
+    }
+
+    fn extend(&mut self, chars: impl IntoIterator<Item = char>) {
+        for c in chars {
+            self.push(c);
+        }
+    }
+
+    fn parse_part(&mut self, part: &str) -> Result<(), String> {
+        if let Some(chars) = named(part) {
+            self.extend(chars);
+            return Ok(());
+        }
+
+        let chars: Vec<char> = part.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] == '[' && chars.get(i + 1) == Some(&':') {
+                let rest: String = chars[i + 2..].iter().collect();
+
+                let end = rest
+                    .find(":]")
+                    .ok_or_else(|| format!("[:{rest} is never closed with :]"))?;
+
+                let class = posix_class(&rest[..end])
+                    .ok_or_else(|| format!("Unknown class [:{}:]", &rest[..end]))?;
+
+                self.extend(class);
+                i += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+
+            let (start, next) = literal(&chars, i)?;
+
+            if chars.get(next) == Some(&'-') && next + 1 < chars.len() {
+                let (end, after) = literal(&chars, next + 1)?;
+
+                if start > end {
+                    return Err(format!(
+                        "Invalid range {start}-{end}, did you mean {end}-{start}?"
+                    ));
+                }
+
+                self.extend(start..=end);
+                i = after;
+            } else {
+                self.push(start);
+                i = next;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("Charset cannot be empty".to_owned());
+        }
+
+        let mut charset = Charset {
+            chars: Vec::new(),
+            set: HashSet::new(),
+        };
+
+        for part in split_parts(s) {
+            // An empty part comes from a `+` without anything on one side,
+            // so treat it as the literal character.
+            if part.is_empty() {
+                charset.push('+');
+                continue;
+            }
+
+            charset.parse_part(&part)?;
+        }
+
+        if charset.chars.is_empty() {
+            return Err("Charset cannot be empty".to_owned());
+        }
+
+        Ok(charset)
+    }
+}
+
+/// Splits on unescaped `+`, keeping escapes intact for the part parser.
+fn split_parts(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let current = parts.last_mut().unwrap();
+                current.push(c);
+
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            '+' => parts.push(String::new()),
+            _ => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    parts
+}
+
+/// Reads a single, possibly escaped, character at `i`.
+/// Returns the character and the index after it.
+fn literal(chars: &[char], i: usize) -> Result<(char, usize), String> {
+    match chars[i] {
+        '\\' => chars
+            .get(i + 1)
+            .map(|c| (*c, i + 2))
+            .ok_or_else(|| "Nothing to escape after the trailing \\".to_owned()),
+        c => Ok((c, i + 1)),
+    }
+}
+
+fn named(name: &str) -> Option<Vec<char>> {
+    let chars = match name {
+        "alpha" => format!("{LOWER}{UPPER}"),
+        "lower" => LOWER.to_owned(),
+        "upper" => UPPER.to_owned(),
+        "digits" => DIGITS.to_owned(),
+        "alnum" => format!("{LOWER}{UPPER}{DIGITS}"),
+        "hex" => format!("{DIGITS}abcdef"),
+        "HEX" => format!("{DIGITS}ABCDEF"),
+        "base64" => format!("{UPPER}{LOWER}{DIGITS}+/"),
+        "base58" => BASE58.to_owned(),
+        "punct" => PUNCT.to_owned(),
+        "whitespace" => WHITESPACE.to_owned(),
+        _ => return None,
+    };
+
+    Some(chars.chars().collect())
+}
+
+fn posix_class(name: &str) -> Option<Vec<char>> {
+    let chars = match name {
+        "alpha" => format!("{LOWER}{UPPER}"),
+        "lower" => LOWER.to_owned(),
+        "upper" => UPPER.to_owned(),
+        "digit" => DIGITS.to_owned(),
+        "alnum" => format!("{LOWER}{UPPER}{DIGITS}"),
+        "xdigit" => format!("{DIGITS}abcdefABCDEF"),
+        "punct" => PUNCT.to_owned(),
+        "space" => WHITESPACE.to_owned(),
+        "blank" => " \t".to_owned(),
+        "cntrl" => (0..32u8).chain([127]).map(char::from).collect(),
+        "graph" => (33..127u8).map(char::from).collect(),
+        "print" => (32..127u8).map(char::from).collect(),
+        _ => return None,
+    };
+
+    Some(chars.chars().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Charset;
+
+    fn chars(charset: &str) -> Vec<char> {
+        charset.parse::<Charset>().unwrap().into_chars()
+    }
+
+    #[test]
+    fn charset_parts() {
+        assert_eq!(chars("abc"), ['a', 'b', 'c']);
+        assert_eq!(chars("a-e"), ['a', 'b', 'c', 'd', 'e']);
+        assert_eq!(chars("hex").len(), 16);
+        assert_eq!(chars("[:digit:]x"), chars("0123456789x"));
+        assert_eq!(chars("digits+_-"), chars("0123456789_-"));
+        assert_eq!(chars("alnum").len(), 62);
+    }
+
+    #[test]
+    fn charset_escapes_and_plus() {
+        assert_eq!(chars(r"a\-z"), ['a', '-', 'z']);
+        assert_eq!(chars(r"\\"), ['\\']);
+        assert_eq!(chars(r"a\+b"), ['a', '+', 'b']);
+        // A + without anything next to it is the literal character
+        assert_eq!(chars("a+"), ['a', '+']);
+    }
+
+    #[test]
+    fn charset_dedupes_in_order() {
+        assert_eq!(chars("cab+abc"), ['c', 'a', 'b']);
+        assert_eq!(chars("\u{100}-\u{D7FF}").len(), 0xD7FF - 0x100 + 1);
+    }
+
+    #[test]
+    fn charset_contains() {
+        let charset: Charset = "a-z+[:digit:]".parse().unwrap();
+        assert!(charset.contains('q'));
+        assert!(charset.contains('7'));
+        assert!(!charset.contains('Q'));
+    }
+
+    #[test]
+    fn charset_errors() {
+        assert!("".parse::<Charset>().is_err());
+        assert!("z-a".parse::<Charset>().is_err());
+        assert!("[:nope:]".parse::<Charset>().is_err());
+        assert!("[:upper".parse::<Charset>().is_err());
+        assert!(r"a\".parse::<Charset>().is_err());
+    }
+}
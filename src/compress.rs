@@ -0,0 +1,368 @@
+use std::{
+    io::{self, Result, Write},
+    mem,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex, OnceLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use flate2::write::GzEncoder;
+use xz2::write::XzEncoder;
+
+use crate::{
+    args::{Compression, Destination, GenArgs},
+    splice::Splice,
+};
+
+/// The amount of uncompressed data in each independently compressed block.
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// How long the start of a block can wait for the rest of it.
+/// Slow streams, e.g. with --rate, are written in smaller blocks instead of stalling.
+const MAX_BLOCK_AGE: Duration = Duration::from_millis(200);
+
+type Block = Receiver<Result<Vec<u8>>>;
+
+/// What the writer thread does next, in the order of the output.
+enum Queued {
+    Block(Block),
+    /// Flushes the output and reports back if anyone is waiting for it.
+    Flush(Option<SyncSender<()>>),
+}
+type Job = (Compression, Vec<u8>, SyncSender<Result<Vec<u8>>>);
+
+/// The threads which compress the blocks of every writer, one per CPU.
+/// They are started with the first writer and shared by all of them,
+/// since splitting the output or serving clients creates many writers at once.
+struct Pool {
+    jobs: SyncSender<Job>,
+    threads: usize,
+}
+
+fn pool() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+
+    POOL.get_or_init(|| {
+        let threads = num_cpus::get();
+        let (jobs, job_receiver) = mpsc::sync_channel::<Job>(threads);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        for _ in 0..threads {
+            let job_receiver = Arc::clone(&job_receiver);
+
+            thread::spawn(move || loop {
+                let job = job_receiver
+                    .lock()
+                    .expect("Failed to lock compression jobs")
+                    .recv();
+
+                let Ok((compression, data, result)) = job else {
+                    return;
+                };
+
+                // The writer is gone if writing the output failed
+                let _ = result.send(compress(compression, &data));
+            });
+        }
+
+        Pool { jobs, threads }
+    })
+}
+
+/// The compression from --compress or the extension of a file destination.
+pub fn compression(args: &GenArgs) -> Option<Compression> {
+    let compression = args.compress.or_else(|| match &args.destination {
+        Some(Destination::File(path)) => path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Compression::from_extension),
+        _ => None,
+    });
+
+    compression.filter(|compression| *compression != Compression::None)
+}
+
+/// Compresses everything written to it on the shared pool of threads.
+///
+/// The data is cut into blocks which are compressed independently and written in order.
+/// All of the formats allow compressed streams to be concatenated
+/// (gzip members, zstd frames, xz streams and lz4 frames),
+/// so the output decompresses to the original data with the usual tools.
+pub struct CompressedWriter {
+    compression: Compression,
+    block: Vec<u8>,
+    /// When the first data of the block was written.
+    started: Option<Instant>,
+    jobs: Option<SyncSender<Job>>,
+    queue: Option<SyncSender<Queued>>,
+    writer: Option<JoinHandle<Result<()>>>,
+}
+
+impl CompressedWriter {
+    pub fn new<W: Write + Send + 'static>(inner: W, compression: Compression) -> Self {
+        let pool = pool();
+
+        // Holds the blocks in the order they were written, whether they are compressed yet or not
+        let (queue, queue_receiver) = mpsc::sync_channel::<Queued>(pool.threads * 2);
+
+        let writer = thread::spawn(move || {
+            let mut inner = inner;
+
+            for queued in queue_receiver {
+                match queued {
+                    Queued::Block(block) => {
+                        let compressed = block
+                            .recv()
+                            .map_err(|_| io::Error::other("A compression thread stopped"))??;
+
+                        inner.write_all(&compressed)?;
+                    }
+                    Queued::Flush(flushed) => {
+                        inner.flush()?;
+
+                        if let Some(flushed) = flushed {
+                            let _ = flushed.send(());
+                        }
+                    }
+                }
+            }
+
+            inner.flush()
+        });
+
+        CompressedWriter {
+            compression,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            started: None,
+            jobs: Some(pool.jobs.clone()),
+            queue: Some(queue),
+            writer: Some(writer),
+        }
+    }
+
+    /// Compresses what is left, waits for everything to be written and flushes the output.
+    pub fn finish(&mut self) -> Result<()> {
+        if !self.block.is_empty() {
+            self.send_block()?;
+        }
+
+        // Closing the channel stops the writer thread once every block is written
+        self.jobs.take();
+        self.queue.take();
+
+        match self.writer.take() {
+            Some(writer) => writer.join().expect("Thread panicked"),
+            None => Ok(()),
+        }
+    }
+
+    fn send_block(&mut self) -> Result<()> {
+        let data = mem::replace(&mut self.block, Vec::with_capacity(BLOCK_SIZE));
+        let (result, block) = mpsc::sync_channel(1);
+        self.started = None;
+
+        let sent = match (&self.queue, &self.jobs) {
+            (Some(queue), Some(jobs)) => {
+                queue.send(Queued::Block(block)).is_ok()
+                    && jobs.send((self.compression, data, result)).is_ok()
+            }
+            _ => false,
+        };
+
+        if sent {
+            return Ok(());
+        }
+
+        self.stopped()
+    }
+
+    /// Why the writer thread stopped.
+    fn stopped(&mut self) -> Result<()> {
+        match self.finish() {
+            Ok(()) => Err(io::ErrorKind::BrokenPipe.into()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Splice for CompressedWriter {}
+
+impl Write for CompressedWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..len]);
+
+        let started = *self.started.get_or_insert_with(Instant::now);
+
+        if self.block.len() == BLOCK_SIZE {
+            self.send_block()?;
+        } else if started.elapsed() >= MAX_BLOCK_AGE {
+            self.send_block()?;
+
+            // Don't wait for the output, the next write can go on with the next block
+            if let Some(queue) = &self.queue {
+                let _ = queue.send(Queued::Flush(None));
+            }
+        }
+
+        Ok(len)
+    }
+
+    /// Compresses the partial block and waits for everything to be written and flushed.
+    fn flush(&mut self) -> Result<()> {
+        if !self.block.is_empty() {
+            self.send_block()?;
+        }
+
+        let (flushed, receiver) = mpsc::sync_channel(1);
+
+        let sent = self
+            .queue
+            .as_ref()
+            .is_some_and(|queue| queue.send(Queued::Flush(Some(flushed))).is_ok());
+
+        if sent && receiver.recv().is_ok() {
+            return Ok(());
+        }
+
+        self.stopped()
+    }
+}
+
+impl Drop for CompressedWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn compress(compression: Compression, data: &[u8]) -> Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Compression::Zstd => zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+        Compression::Xz => {
+            let mut encoder = XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Compression::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+            encoder.write_all(data)?;
+            encoder.finish().map_err(io::Error::other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use rand::Rng;
+
+    use super::{CompressedWriter, BLOCK_SIZE};
+    use crate::args::Compression;
+
+    /// An output which can be looked at while the writer still has it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn decompress(&self, compression: Compression) -> Vec<u8> {
+            let compressed = self.0.lock().unwrap().clone();
+            let mut data = vec![];
+
+            match compression {
+                Compression::None => data = compressed,
+                Compression::Gzip => {
+                    flate2::read::MultiGzDecoder::new(&compressed[..])
+                        .read_to_end(&mut data)
+                        .unwrap();
+                }
+                Compression::Zstd => data = zstd::decode_all(&compressed[..]).unwrap(),
+                Compression::Xz => {
+                    xz2::read::XzDecoder::new_multi_decoder(&compressed[..])
+                        .read_to_end(&mut data)
+                        .unwrap();
+                }
+                Compression::Lz4 => {
+                    // The decoder stops after one frame
+                    let mut rest = &compressed[..];
+
+                    while !rest.is_empty() {
+                        lz4_flex::frame::FrameDecoder::new(&mut rest)
+                            .read_to_end(&mut data)
+                            .unwrap();
+                    }
+                }
+            }
+
+            data
+        }
+    }
+
+    const FORMATS: [Compression; 4] = [
+        Compression::Gzip,
+        Compression::Zstd,
+        Compression::Xz,
+        Compression::Lz4,
+    ];
+
+    #[test]
+    fn blocks_concatenate() {
+        let mut data = vec![0; 2 * BLOCK_SIZE + 1000];
+        rand::thread_rng().fill(&mut data[..BLOCK_SIZE]);
+
+        for compression in FORMATS {
+            let output = Shared::default();
+            let mut writer = CompressedWriter::new(output.clone(), compression);
+
+            for chunk in data.chunks(100_000) {
+                writer.write_all(chunk).unwrap();
+            }
+
+            writer.finish().unwrap();
+            assert!(output.decompress(compression) == data, "{compression:?}");
+        }
+    }
+
+    #[test]
+    fn flush_writes_partial_block() {
+        for compression in FORMATS {
+            let output = Shared::default();
+            let mut writer = CompressedWriter::new(output.clone(), compression);
+
+            writer.write_all(b"first").unwrap();
+            writer.flush().unwrap();
+            assert_eq!(output.decompress(compression), b"first", "{compression:?}");
+
+            // Flushing again without new data doesn't add an empty block
+            let len = output.0.lock().unwrap().len();
+            writer.flush().unwrap();
+            assert_eq!(output.0.lock().unwrap().len(), len);
+
+            writer.write_all(b" second").unwrap();
+            writer.finish().unwrap();
+            assert_eq!(output.decompress(compression), b"first second");
+        }
+    }
+}
@@ -0,0 +1,529 @@
+use std::{collections::HashSet, ffi::OsString, fs, io, path::PathBuf};
+
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
+use serde_json::Value;
+
+use crate::{
+    args::GenArgs,
+    error::{Error, Result},
+    server,
+};
+
+/// Points to a config file to use instead of the one in the config directory,
+/// e.g. one versioned with a project.
+const CONFIG_VAR: &str = "GEN_CONFIG";
+
+/// Defaults and presets from the config file, e.g. ~/.config/gen/config.toml:
+///
+///   [defaults]
+///   threads = 4
+///   progress = true
+///
+///   [defaults.ascii]
+///   buf-size = "1MiB"
+///
+///   [preset.apikey]
+///   cmd = "ascii"
+///   charset = "alnum"
+///   size = "32B"
+///
+/// Keys are the long or short names of arguments, the same as in JSON requests to `gen serve`.
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    path: PathBuf,
+    /// Arguments for every command which has them,
+    /// followed by tables of arguments for a single command which take precedence.
+    defaults: toml::Table,
+    /// Named sets of arguments for a command, run with `gen @<name>`.
+    presets: toml::Table,
+}
+
+/// Parses the command line with the config applied.
+/// The command line takes precedence over a preset, which takes precedence over the defaults.
+pub fn parse_args() -> Result<GenArgs> {
+    let config = Config::load()?;
+    let args = config.apply(std::env::args_os().collect())?;
+    let mut matches = GenArgs::command().get_matches_from(args);
+
+    Ok(GenArgs::from_arg_matches_mut(&mut matches).unwrap_or_else(|e| e.exit()))
+}
+
+impl Config {
+    /// Reads the config file. It's fine for there to be none,
+    /// unless it was asked for with GEN_CONFIG.
+    fn load() -> Result<Config> {
+        let (path, required) = match std::env::var_os(CONFIG_VAR) {
+            Some(path) => (PathBuf::from(path), true),
+            None => match dirs::config_dir() {
+                Some(dir) => (dir.join("gen").join("config.toml"), false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let config = Config {
+            path,
+            ..Config::default()
+        };
+
+        match fs::read_to_string(&config.path) {
+            Ok(text) => config.parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(config),
+            Err(e) => Err(config.error(e)),
+        }
+    }
+
+    /// Reads the tables of the config file.
+    pub(crate) fn parse(mut self, text: &str) -> Result<Config> {
+        let config = &mut self;
+        let mut table: toml::Table = text.parse().map_err(|e| config.error(e))?;
+
+        config.defaults = config.take_table(&mut table, "defaults")?;
+        config.presets = config.take_table(&mut table, "preset")?;
+
+        if let Some(key) = table.keys().next() {
+            return Err(config.error(format!(
+                "Unknown key {key}, expected [defaults] or [preset.<name>]"
+            )));
+        }
+
+        Ok(self)
+    }
+
+    fn take_table(&self, table: &mut toml::Table, key: &str) -> Result<toml::Table> {
+        match table.remove(key) {
+            None => Ok(toml::Table::new()),
+            Some(toml::Value::Table(table)) => Ok(table),
+            Some(_) => Err(self.error(format!("{key} must be a table"))),
+        }
+    }
+
+    /// Expands a preset in place of the subcommand and adds the defaults.
+    /// Arguments from the config are left out when the command line or,
+    /// for the defaults, the preset already sets them.
+    pub(crate) fn apply(&self, mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+        let command = GenArgs::command();
+
+        let Some(index) = subcommand_index(&command, &args) else {
+            return Ok(args);
+        };
+
+        let word = args[index].to_string_lossy().into_owned();
+
+        let (cmd, preset) = match word.strip_prefix('@') {
+            Some(name) => (self.preset_command(name)?, Some(name)),
+            None => (word, None),
+        };
+
+        // Leave unknown commands for clap to report
+        let Some(subcommand) = command.find_subcommand(&cmd) else {
+            return Ok(args);
+        };
+
+        let rest = args.split_off(index + 1);
+        args.truncate(index);
+
+        let mut words = Words::default();
+        words.set_given(&command, &args, &cmd, &rest);
+
+        if let Some(name) = preset {
+            self.push_preset(&command, subcommand, name, &mut words)?;
+        }
+
+        self.push_defaults(&command, subcommand, &mut words)?;
+
+        let mut expanded = vec![args.remove(0)];
+        expanded.extend(words.global.words.into_iter().map(OsString::from));
+        expanded.extend(args);
+        expanded.push(cmd.into());
+        expanded.extend(words.local.words.into_iter().map(OsString::from));
+        expanded.extend(rest);
+
+        // Positional values like -5..5 would otherwise be mistaken for flags
+        if !words.positionals.is_empty() {
+            expanded.push("--".into());
+            expanded.extend(words.positionals.into_iter().map(OsString::from));
+        }
+
+        Ok(expanded)
+    }
+
+    /// Adds the defaults which apply to `subcommand`.
+    /// Defaults for arguments that other commands have are skipped, e.g. --buf-size for tree.
+    fn push_defaults(
+        &self,
+        command: &clap::Command,
+        subcommand: &clap::Command,
+        words: &mut Words,
+    ) -> Result<()> {
+        let name = subcommand.get_name();
+
+        let command_defaults = match self.defaults.get(name) {
+            Some(toml::Value::Table(defaults)) => Some(defaults),
+            _ => None,
+        };
+
+        for (key, value) in &self.defaults {
+            match value {
+                // Overridden for this command, maybe because the value doesn't suit it,
+                // e.g. --buf-size counts values for int but bytes for ascii
+                _ if command_defaults.is_some_and(|defaults| defaults.contains_key(key)) => {}
+                toml::Value::Table(_) => {
+                    if command.find_subcommand(key).is_none() {
+                        return Err(
+                            self.error(format!("Unknown command {key} in [defaults.{key}]"))
+                        );
+                    }
+                }
+                value => {
+                    if !self.push_flag(command, subcommand, key, value, words)?
+                        && !command
+                            .get_subcommands()
+                            .any(|subcommand| server::find_flag(subcommand, key).is_some())
+                    {
+                        return Err(self.error(format!("Unknown argument {key} in [defaults]")));
+                    }
+                }
+            }
+        }
+
+        if let Some(defaults) = command_defaults {
+            for (key, value) in defaults {
+                if !self.push_flag(command, subcommand, key, value, words)? {
+                    return Err(self.error(format!(
+                        "Unknown argument {key} for {name} in [defaults.{name}]"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The command a preset runs.
+    fn preset_command(&self, name: &str) -> Result<String> {
+        let Some(preset) = self.presets.get(name) else {
+            let names: Vec<String> = self.presets.keys().map(|name| format!("@{name}")).collect();
+
+            return Err(if names.is_empty() {
+                self.error(format!("Unknown preset @{name}, no presets are defined"))
+            } else {
+                self.error(format!(
+                    "Unknown preset @{name}, expected one of {}",
+                    names.join(", ")
+                ))
+            });
+        };
+
+        match preset.get("cmd") {
+            Some(toml::Value::String(cmd)) => Ok(cmd.clone()),
+            _ => Err(self.error(format!(
+                "[preset.{name}] must have a cmd, e.g. cmd = \"ascii\""
+            ))),
+        }
+    }
+
+    fn push_preset(
+        &self,
+        command: &clap::Command,
+        subcommand: &clap::Command,
+        name: &str,
+        words: &mut Words,
+    ) -> Result<()> {
+        let Some(toml::Value::Table(preset)) = self.presets.get(name) else {
+            return Err(self.error(format!("[preset.{name}] must be a table")));
+        };
+
+        for positional in subcommand.get_positionals() {
+            if !words.local.given.insert(positional.get_id().to_string()) {
+                continue;
+            }
+
+            if let Some(value) = preset.get(positional.get_id().as_str()) {
+                let value = server::json_value_to_string(&self.to_json(value)?)
+                    .map_err(|e| self.error(e.trim_end()))?;
+
+                words.positionals.push(value);
+            }
+        }
+
+        for (key, value) in preset {
+            if key == "cmd"
+                || subcommand
+                    .get_positionals()
+                    .any(|positional| positional.get_id().as_str() == key)
+            {
+                continue;
+            }
+
+            if !self.push_flag(command, subcommand, key, value, words)? {
+                return Err(self.error(format!(
+                    "Unknown argument {key} for {} in [preset.{name}]",
+                    subcommand.get_name()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the flag named `key` of either the subcommand or gen itself,
+    /// unless it's already given. Returns whether there is such a flag.
+    fn push_flag(
+        &self,
+        command: &clap::Command,
+        subcommand: &clap::Command,
+        key: &str,
+        value: &toml::Value,
+        words: &mut Words,
+    ) -> Result<bool> {
+        let (arg, args) = if let Some(arg) = server::find_flag(subcommand, key) {
+            (arg, &mut words.local)
+        } else if let Some(arg) = server::find_flag(command, key) {
+            (arg, &mut words.global)
+        } else {
+            return Ok(false);
+        };
+
+        // Also validates the value when it's overridden
+        let flag =
+            server::flag_words(arg, self.to_json(value)?).map_err(|e| self.error(e.trim_end()))?;
+
+        if args.given.insert(arg.get_id().to_string()) {
+            args.words.extend(flag);
+        }
+
+        Ok(true)
+    }
+
+    fn to_json(&self, value: &toml::Value) -> Result<Value> {
+        serde_json::to_value(value).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> Error {
+        Error::input(format!("{}: {message}", self.path.display()))
+    }
+}
+
+/// The command line words from the config.
+#[derive(Default)]
+struct Words {
+    /// Arguments of gen itself, which go before the subcommand.
+    global: Args,
+    /// Arguments of the subcommand.
+    local: Args,
+    positionals: Vec<String>,
+}
+
+#[derive(Default)]
+struct Args {
+    words: Vec<String>,
+    /// The ids of the arguments which are already set, by the command line or the preset.
+    given: HashSet<String>,
+}
+
+impl Words {
+    /// Marks the arguments on the command line as given,
+    /// so that the config doesn't set them a second time.
+    fn set_given(
+        &mut self,
+        command: &clap::Command,
+        args: &[OsString],
+        cmd: &str,
+        rest: &[OsString],
+    ) {
+        let words = args
+            .iter()
+            .cloned()
+            .chain([cmd.into()])
+            .chain(rest.iter().cloned());
+
+        // Arguments which are missing or invalid are reported when parsing with the config
+        let Ok(matches) = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(words)
+        else {
+            return;
+        };
+
+        self.global.given = given(&matches);
+
+        if let Some(matches) = matches.subcommand_matches(cmd) {
+            self.local.given = given(matches);
+        }
+    }
+}
+
+fn given(matches: &ArgMatches) -> HashSet<String> {
+    matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(|id| id.to_string())
+        .collect()
+}
+
+/// The index of the subcommand or preset, after the arguments of gen itself.
+fn subcommand_index(command: &clap::Command, args: &[OsString]) -> Option<usize> {
+    let mut words = args.iter().enumerate().skip(1);
+
+    while let Some((i, word)) = words.next() {
+        let word = word.to_str()?;
+
+        let arg = if let Some(long) = word.strip_prefix("--") {
+            if long.is_empty() {
+                return None;
+            }
+
+            command
+                .get_arguments()
+                .find(|arg| !long.contains('=') && arg.get_long() == Some(long))
+        } else if let Some(short) = word.strip_prefix('-') {
+            let mut chars = short.chars();
+
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => command
+                    .get_arguments()
+                    .find(|arg| arg.get_short() == Some(c)),
+                _ => None,
+            }
+        } else {
+            return Some(i);
+        };
+
+        // Skip the value of the argument
+        if arg.is_some_and(|arg| arg.get_action().takes_values()) {
+            words.next();
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use clap::Parser;
+
+    use super::Config;
+    use crate::args::GenArgs;
+
+    const CONFIG: &str = r#"
+        [defaults]
+        threads = 4
+        progress = true
+
+        [defaults.ascii]
+        buf-size = "1MiB"
+
+        [preset.small]
+        cmd = "int"
+        range = "0..5"
+        threads = 2
+
+        [preset.quiet]
+        cmd = "ascii"
+        charset = "alnum"
+        progress = false
+    "#;
+
+    fn expand(args: &str) -> Vec<String> {
+        let config = Config::default().parse(CONFIG).unwrap();
+        let args = args.split(' ').map(OsString::from).collect();
+
+        config
+            .apply(args)
+            .unwrap()
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn config_presets_and_defaults() {
+        assert_eq!(
+            expand("gen @small"),
+            ["gen", "int", "--threads=2", "--progress", "--", "0..5"]
+        );
+
+        assert_eq!(
+            expand("gen ascii -s 1KB"),
+            [
+                "gen",
+                "ascii",
+                "--progress",
+                "--threads=4",
+                "--buf-size=1MiB",
+                "-s",
+                "1KB"
+            ]
+        );
+
+        // The preset turns off the progress bar of the defaults
+        assert_eq!(
+            expand("gen @quiet"),
+            [
+                "gen",
+                "ascii",
+                "--charset=alnum",
+                "--threads=4",
+                "--buf-size=1MiB"
+            ]
+        );
+    }
+
+    #[test]
+    fn config_is_overridden_by_command_line() {
+        assert_eq!(
+            expand("gen @small 100..200"),
+            ["gen", "int", "--threads=2", "--progress", "100..200"]
+        );
+
+        assert_eq!(
+            expand("gen @small -t 8 -p -- -10..10"),
+            ["gen", "int", "-t", "8", "-p", "--", "-10..10"]
+        );
+
+        let args = expand("gen @small 100..200");
+        assert!(GenArgs::try_parse_from(args).is_ok());
+    }
+
+    #[test]
+    fn config_errors() {
+        let error = |config: &str, args: &str| {
+            let args = args.split(' ').map(OsString::from).collect();
+
+            Config::default()
+                .parse(config)
+                .and_then(|config| config.apply(args))
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(error("[other]", "gen int")
+            .ends_with("Unknown key other, expected [defaults] or [preset.<name>]"));
+        assert!(error("defaults = 1", "gen int").ends_with("defaults must be a table"));
+        assert!(error(CONFIG, "gen @big")
+            .ends_with("Unknown preset @big, expected one of @quiet, @small"));
+        assert!(error("", "gen @big").ends_with("Unknown preset @big, no presets are defined"));
+        assert!(error("[preset.x]\nsize = \"1B\"", "gen @x")
+            .ends_with("[preset.x] must have a cmd, e.g. cmd = \"ascii\""));
+        assert!(error("[defaults]\nsise = 1", "gen int")
+            .ends_with("Unknown argument sise in [defaults]"));
+        assert!(error("[defaults.int]\nsize = \"1B\"", "gen int")
+            .ends_with("Unknown argument size for int in [defaults.int]"));
+        assert!(
+            error("[defaults.foo]", "gen int").ends_with("Unknown command foo in [defaults.foo]")
+        );
+        assert!(
+            error("[preset.x]\ncmd = \"int\"\ncharset = \"a\"", "gen @x")
+                .ends_with("Unknown argument charset for int in [preset.x]")
+        );
+    }
+
+    #[test]
+    fn repeated_flags_are_rejected() {
+        assert!(GenArgs::try_parse_from(["gen", "ascii", "-c", "a", "-c", "b"]).is_err());
+        assert!(GenArgs::try_parse_from(expand("gen @quiet -c digit")).is_ok());
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Stops generation once a duration has passed
+/// and keeps count of what was produced until then across threads.
+#[derive(Debug)]
+pub struct Deadline {
+    started: Instant,
+    duration: Duration,
+    produced: AtomicUsize,
+}
+
+impl Deadline {
+    pub fn new(duration: Duration) -> Self {
+        Deadline {
+            started: Instant::now(),
+            duration,
+            produced: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn has_passed(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+
+    pub fn add(&self, amount: usize) {
+        self.produced.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    /// Prints the total produced to stderr. `unit` names what was counted.
+    pub fn report(&self, unit: &str) {
+        eprintln!(
+            "Generated {} {unit} in {:.2?}",
+            self.produced.load(Ordering::Relaxed),
+            self.started.elapsed()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::Ordering, thread, time::Duration};
+
+    use super::Deadline;
+
+    #[test]
+    fn passes_after_the_duration() {
+        let deadline = Deadline::new(Duration::from_millis(200));
+        assert!(!deadline.has_passed());
+
+        thread::sleep(Duration::from_millis(250));
+        assert!(deadline.has_passed());
+
+        assert!(Deadline::new(Duration::ZERO).has_passed());
+    }
+
+    #[test]
+    fn counts_across_threads() {
+        let deadline = Deadline::new(Duration::from_secs(60));
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        deadline.add(3);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(deadline.produced.load(Ordering::Relaxed), 12_000);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+
+use rand::Rng;
+
+use crate::{
+    args::{ByteSize, Ratio},
+    error::{Error, Result},
+};
+
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+const DEFAULT_DISTANCE: usize = 1024;
+
+/// How much of the output repeats earlier blocks.
+#[derive(Debug, Clone, Copy)]
+pub struct Dedupe {
+    pub ratio: f64,
+    pub block_size: usize,
+    /// How many of the most recent unique blocks a repeated block can be copied from.
+    pub distance: usize,
+}
+
+impl Dedupe {
+    pub fn new(
+        ratio: Ratio,
+        block_size: Option<ByteSize>,
+        distance: Option<NonZeroUsize>,
+    ) -> Result<Self> {
+        let block_size = block_size.map_or(DEFAULT_BLOCK_SIZE, |b| b.to_bytes());
+
+        if block_size == 0 {
+            return Err(Error::input("Dedupe block size must be greater than 0"));
+        }
+
+        Ok(Dedupe {
+            ratio: ratio.value,
+            block_size,
+            distance: distance.map_or(DEFAULT_DISTANCE, NonZeroUsize::get),
+        })
+    }
+}
+
+/// Replaces blocks of generated data with copies of earlier blocks.
+/// Each thread has its own, sharing the history of unique blocks with the others
+/// so that blocks repeat across threads too. Since every write is made of whole
+/// blocks the blocks stay aligned in the output.
+pub struct Deduplicator {
+    dedupe: Dedupe,
+    history: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    /// The number of repeated blocks which are due.
+    /// Carried over so that the ratio is exact over the whole output.
+    due: f64,
+}
+
+impl Deduplicator {
+    pub fn new(dedupe: Dedupe) -> Self {
+        Deduplicator {
+            dedupe,
+            history: Arc::new(Mutex::new(VecDeque::with_capacity(dedupe.distance))),
+            due: 0.0,
+        }
+    }
+
+    /// Another deduplicator for a different thread, with the same history.
+    pub fn share(&self) -> Self {
+        Deduplicator {
+            dedupe: self.dedupe,
+            history: Arc::clone(&self.history),
+            due: 0.0,
+        }
+    }
+
+    /// Repeats earlier blocks in place of whole blocks of the buffer.
+    /// A partial block at the end is left as it is.
+    pub fn apply(&mut self, buffer: &mut [u8]) {
+        let mut rng = rand::thread_rng();
+
+        // Once per buffer rather than per block, to keep the threads from waiting on each other
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+
+        for block in buffer.chunks_exact_mut(self.dedupe.block_size) {
+            self.due += self.dedupe.ratio;
+
+            if self.due >= 1.0 && !history.is_empty() {
+                self.due -= 1.0;
+
+                let index = rng.gen_range(0..history.len());
+                block.copy_from_slice(&history[index]);
+                continue;
+            }
+
+            // Reuse the allocation of the block which falls out of the history
+            let mut unique = if history.len() >= self.dedupe.distance {
+                history.pop_front().unwrap_or_default()
+            } else {
+                Vec::with_capacity(block.len())
+            };
+
+            unique.clear();
+            unique.extend_from_slice(block);
+            history.push_back(unique);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::{Dedupe, Deduplicator};
+
+    const BLOCK_SIZE: usize = 16;
+
+    fn dedupe(ratio: f64) -> Dedupe {
+        Dedupe {
+            ratio,
+            block_size: BLOCK_SIZE,
+            distance: 1024,
+        }
+    }
+
+    #[test]
+    fn repeats_blocks_of_other_threads() {
+        let mut first = Deduplicator::new(dedupe(0.0));
+
+        // Like share, but repeating every block
+        let mut second = Deduplicator {
+            dedupe: dedupe(1.0),
+            ..first.share()
+        };
+
+        // Another thread fills the shared history
+        let mut written: Vec<u8> = (0..8 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+
+        thread::scope(|scope| {
+            scope.spawn(|| first.apply(&mut written));
+        });
+
+        let mut buffer = vec![u8::MAX; 8 * BLOCK_SIZE];
+        second.apply(&mut buffer);
+
+        for block in buffer.chunks_exact(BLOCK_SIZE) {
+            assert!(written.chunks_exact(BLOCK_SIZE).any(|w| w == block));
+        }
+    }
+
+    #[test]
+    fn keeps_the_ratio() {
+        let mut deduplicator = Deduplicator::new(dedupe(0.25));
+        let mut buffer: Vec<u8> = (0..100 * BLOCK_SIZE)
+            .map(|i| (i / BLOCK_SIZE) as u8)
+            .collect();
+
+        deduplicator.apply(&mut buffer);
+
+        let mut blocks: Vec<_> = buffer.chunks_exact(BLOCK_SIZE).collect();
+        blocks.sort();
+        blocks.dedup();
+
+        assert_eq!(blocks.len(), 75);
+    }
+}
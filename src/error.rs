@@ -0,0 +1,81 @@
+use std::{fmt, io, process::ExitCode};
+
+/// Why gen failed. Each kind has its own exit code,
+/// so that scripts can tell bad input apart from I/O failures.
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments don't make sense together, or a request was invalid.
+    Input(String),
+    /// The destination couldn't be created or opened.
+    Destination(String),
+    /// Generating or writing the output failed.
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn input(message: impl Into<String>) -> Self {
+        Error::Input(message.into())
+    }
+
+    /// `target` names what couldn't be opened, e.g. a path or an address.
+    pub fn destination(target: impl fmt::Display, error: io::Error) -> Self {
+        Error::Destination(format!("{target}: {error}"))
+    }
+
+    /// Whoever reads the output stopped reading, e.g. `gen int -n 1B | head`.
+    /// That's how pipelines end, so it isn't reported as a failure.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, Error::Io(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+
+    /// The reader of a daemon or the client of a server went away, which ends its stream.
+    pub fn is_disconnect(&self) -> bool {
+        matches!(
+            self,
+            Error::Io(e) if matches!(
+                e.kind(),
+                io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+            )
+        )
+    }
+
+    /// 2 for bad input like clap's usage errors, 3 when the destination can't be opened
+    /// and 4 when writing fails.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::Input(_) => ExitCode::from(2),
+            Error::Destination(_) => ExitCode::from(3),
+            Error::Io(_) => ExitCode::from(4),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Input(message) | Error::Destination(message) => f.write_str(message),
+            Error::Io(e) => write!(f, "Failed to write the output: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// For the server, which reports errors per client.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Input(message) => io::Error::new(io::ErrorKind::InvalidInput, message),
+            Error::Destination(message) => io::Error::other(message),
+            Error::Io(e) => e,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::Write;
+
+use uuid::Uuid;
+
+/// Appends a value to a buffer as text, the same as `Display` but without allocating.
+pub trait Format {
+    fn format(&self, buffer: &mut Vec<u8>);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.format(&mut buffer);
+        buffer
+    }
+}
+
+impl Format for i64 {
+    fn format(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(itoa::Buffer::new().format(*self).as_bytes());
+    }
+}
+
+/// `Display` itself, since its digits can differ from other shortest representations.
+impl Format for f64 {
+    fn format(&self, buffer: &mut Vec<u8>) {
+        write!(buffer, "{self}").expect("Failed to format float");
+    }
+}
+
+impl Format for Uuid {
+    fn format(&self, buffer: &mut Vec<u8>) {
+        let mut encoded = Uuid::encode_buffer();
+        buffer.extend_from_slice(self.hyphenated().encode_lower(&mut encoded).as_bytes());
+    }
+}
+
+impl Format for String {
+    fn format(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+    use uuid::Uuid;
+
+    use super::Format;
+
+    fn format(value: impl Format) -> String {
+        String::from_utf8(value.to_bytes()).unwrap()
+    }
+
+    #[test]
+    fn floats_like_display() {
+        let values = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.5,
+            0.1,
+            0.3,
+            1.0 / 3.0,
+            123456.789,
+            1e-7,
+            -2.5e-10,
+            1e15,
+            1e16,
+            1.5e21,
+            1e100,
+            f64::MAX,
+            f64::MIN,
+            f64::MIN_POSITIVE,
+            f64::EPSILON,
+            5e-324,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+        ];
+
+        for value in values {
+            assert_eq!(format(value), value.to_string());
+        }
+    }
+
+    #[test]
+    fn any_float_like_display() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100_000 {
+            // Any float, of any magnitude
+            let value = f64::from_bits(rng.gen());
+            assert_eq!(format(value), value.to_string());
+        }
+    }
+
+    #[test]
+    fn floats_in_ranges() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100_000 {
+            let value: f64 = rng.gen_range(-1000.0..1000.0);
+            assert_eq!(format(value), value.to_string());
+        }
+    }
+
+    #[test]
+    fn other_values() {
+        assert_eq!(format(0i64), "0");
+        assert_eq!(format(i64::MIN), i64::MIN.to_string());
+        assert_eq!(format("text".to_owned()), "text");
+
+        let uuid = Uuid::new_v4();
+        assert_eq!(format(uuid), uuid.to_string());
+    }
+}
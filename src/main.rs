@@ -1,59 +1,314 @@
 mod args;
+mod charset;
+mod compress;
+mod config;
+mod deadline;
+mod dedupe;
+mod error;
+mod format;
+mod malformed;
+mod pipeline;
+mod positional;
 mod program;
+mod rate_limit;
+mod regex;
+mod server;
+mod sink;
+mod splice;
+mod split;
+mod template;
+mod tree;
+mod unicode_tables;
+mod url;
 
 use std::{
     self, fs,
-    io::{self, Result, Write},
+    io::{self, Write},
+    net::{TcpListener, TcpStream},
     path::{Path, PathBuf},
+    process::ExitCode,
     sync::{Arc, Mutex},
 };
 
-use args::GenArgs;
-use clap::Parser;
+use args::{Command, Destination, GenArgs};
+use compress::{compression, CompressedWriter};
+use error::{Error, Result};
+use positional::PositionalFile;
+use sink::UdpSink;
+use splice::Splice;
 
 #[cfg(windows)]
 use miow::pipe::{NamedPipe, NamedPipeBuilder};
 
-fn main() -> Result<()> {
-    let args = GenArgs::parse();
+fn main() -> ExitCode {
+    let args = match config::parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return e.exit_code();
+        }
+    };
+
+    match try_main(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        // Whoever reads the output has all they wanted, e.g. `gen int | head`
+        Err(e) if e.is_broken_pipe() => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            e.exit_code()
+        }
+    }
+}
+
+fn try_main(args: GenArgs) -> Result<()> {
+    if let Command::Serve { socket, http } = &args.commands {
+        return server::serve(socket.clone(), *http);
+    }
+
+    if let Command::Tree { .. } = &args.commands {
+        return tree::run(&args);
+    }
+
+    if args.split_size.is_some() || args.split_lines.is_some() || args.files.is_some() {
+        let Some(Destination::File(dest)) = &args.destination else {
+            return Err(Error::input(
+                "Splitting the output requires a file destination",
+            ));
+        };
+
+        return split::run(dest, &args);
+    }
+
+    let file_options =
+        args.sparse || args.preallocate || args.append || args.no_clobber || args.atomic;
+
+    if file_options && !matches!(args.destination, Some(Destination::File(_))) {
+        return Err(Error::input(
+            "--sparse, --preallocate, --append, --no-clobber and --atomic require a file destination",
+        ));
+    }
 
     match (&args.destination, &args.daemon) {
-        (Some(dest), true) => {
+        (Some(Destination::File(dest)), true) => {
             run_daemon(dest, &args)?;
         }
-        (Some(dest), false) => {
+        (Some(Destination::TcpListen(addr)), true) => {
+            run_tcp_daemon(addr, &args)?;
+        }
+        (Some(_), true) => {
+            return Err(Error::input(
+                "--daemon only supports file and tcp-listen:// destinations",
+            ));
+        }
+        (Some(Destination::File(dest)), false) => {
             let dest = std::path::Path::new(dest);
 
             if let Some(parent) = dest.parent() {
-                fs::create_dir_all(parent)
-                    .unwrap_or_else(|_| panic!("Failed to create directories for {parent:?}"));
+                create_dirs(parent)?;
             }
 
-            let writer = fs::File::create(dest)
-                .unwrap_or_else(|_| panic!("Failed to create file {dest:?}"));
+            run_file(dest, &args)?;
+        }
+        (Some(Destination::Tcp(addr)), false) => {
+            let stream = TcpStream::connect(addr)
+                .map_err(|e| Error::destination(format!("tcp://{addr}"), e))?;
+            run(&args, stream)?;
+        }
+        (Some(Destination::TcpListen(addr)), false) => {
+            let (stream, _) = TcpListener::bind(addr)
+                .and_then(|listener| listener.accept())
+                .map_err(|e| Error::destination(format!("tcp-listen://{addr}"), e))?;
+            run(&args, stream)?;
+        }
+        (Some(Destination::Udp(addr)), false) => {
+            let datagram_size = args
+                .datagram_size
+                .map_or(DEFAULT_DATAGRAM_SIZE, |size| size.bytes);
+
+            let packets_per_second = args.packets_per_second.map(|pps| pps.get());
 
-            program::run(args.clone(), Arc::new(Mutex::new(writer)));
+            let sink = UdpSink::connect(addr, datagram_size, packets_per_second)
+                .map_err(|e| Error::destination(format!("udp://{addr}"), e))?;
+            run(&args, sink)?;
         }
         (None, _) => {
-            let writer = io::stdout();
-            program::run(args.clone(), Arc::new(Mutex::new(writer)));
+            run(&args, io::stdout())?;
         }
     };
 
     Ok(())
 }
 
+fn create_dirs(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).map_err(|e| Error::destination(dir.display(), e))
+}
+
+/// The largest UDP payload which fits in a single Ethernet frame.
+const DEFAULT_DATAGRAM_SIZE: usize = 1472;
+
+fn run<T: Write + Splice + Send + 'static>(args: &GenArgs, writer: T) -> Result<()> {
+    if let Some(compression) = compression(args) {
+        let writer = CompressedWriter::new(writer, compression);
+        let writer = Arc::new(Mutex::new(writer));
+        program::run(args.clone(), writer.clone(), None)?;
+
+        let mut writer = writer.lock().expect("Failed to lock writer");
+        return Ok(writer.finish()?);
+    }
+
+    let writer = Arc::new(Mutex::new(writer));
+    program::run(args.clone(), writer.clone(), None)?;
+
+    // Some writers hold on to data until they are flushed
+    let mut writer = writer.lock().expect("Failed to lock writer");
+    Ok(writer.flush()?)
+}
+
+/// Writes to a file, letting every thread write its own part of it
+/// when the size of the output is known up front.
+fn run_file(path: &Path, args: &GenArgs) -> Result<()> {
+    // Named pipes can only be written in order
+    if is_fifo(path) {
+        if args.sparse || args.preallocate || args.atomic {
+            return Err(Error::input(
+                "--sparse, --preallocate and --atomic can't be used with a named pipe",
+            ));
+        }
+
+        return run(args, open_file(path, args)?);
+    }
+
+    // Appending can only write in order too
+    let size = if args.append {
+        None
+    } else {
+        positional::output_size(args)?
+    };
+
+    // An existing file is also replaced only once the output is complete,
+    // so a run which fails, e.g. on its arguments, leaves it untouched
+    let replace = !args.append && !args.no_clobber && is_file(path);
+
+    if !args.atomic && !replace {
+        return write_file(open_file(path, args)?, size, args);
+    }
+
+    // Fail before generating anything rather than when moving the file into place
+    if args.no_clobber && path.exists() {
+        return Err(already_exists(path));
+    }
+
+    let temp = temp_path(path);
+
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .map_err(|e| Error::destination(temp.display(), e))
+        .and_then(|file| {
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())
+                    .map_err(|e| Error::destination(temp.display(), e))?;
+            }
+
+            write_file(file, size, args)
+        })
+        .and_then(|()| persist(&temp, path, args.no_clobber));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result
+}
+
+fn write_file(file: fs::File, size: Option<usize>, args: &GenArgs) -> Result<()> {
+    let Some(size) = size else {
+        return run(args, file);
+    };
+
+    let file = Arc::new(PositionalFile::new(
+        file,
+        size,
+        args.sparse,
+        args.preallocate,
+    )?);
+
+    let writer = Arc::new(Mutex::new(file.at(0, size)));
+    program::run(args.clone(), writer, Some(file))
+}
+
+/// Opens a file destination for --append, --no-clobber or to overwrite it.
+fn open_file(path: &Path, args: &GenArgs) -> Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+
+    if args.append {
+        options.append(true).create(true);
+    } else if args.no_clobber {
+        options.create_new(true);
+    } else {
+        options.create(true).truncate(true);
+    }
+
+    options.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => already_exists(path),
+        _ => Error::destination(path.display(), e),
+    })
+}
+
+/// A hidden file next to the destination, so that it can be renamed into place.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+/// Moves the finished temporary file to the destination.
+fn persist(temp: &Path, path: &Path, no_clobber: bool) -> Result<()> {
+    if !no_clobber {
+        return fs::rename(temp, path).map_err(|e| Error::destination(path.display(), e));
+    }
+
+    // Unlike renaming, linking fails if the destination was created in the meantime
+    fs::hard_link(temp, path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => already_exists(path),
+        _ => Error::destination(path.display(), e),
+    })?;
+
+    Ok(fs::remove_file(temp)?)
+}
+
+fn already_exists(path: &Path) -> Error {
+    Error::Destination(format!(
+        "{} already exists, remove it or leave out --no-clobber",
+        path.display()
+    ))
+}
+
+/// Whether the path is an existing regular file rather than e.g. a symlink or device.
+fn is_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_file())
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    false
+}
+
 fn run_daemon(path: &Path, args: &GenArgs) -> Result<()> {
     loop {
-        let pipe = DaemonWriter::new(path)?;
+        let mut pipe =
+            DaemonWriter::new(path).map_err(|e| Error::destination(path.display(), e))?;
 
         match pipe.connect() {
-            Ok(()) => {
-                // Handle the connection, e.g., spawn a thread to handle requests
-                // For now, we just print a message
-                let writer = Arc::new(Mutex::new(pipe));
-                program::run(args.clone(), writer.clone());
-            }
+            Ok(()) => serve_reader(args, pipe)?,
             Err(e) => {
                 eprintln!("Failed to connect to daemon: {e}");
                 std::thread::sleep(std::time::Duration::from_secs(1));
@@ -62,31 +317,79 @@ fn run_daemon(path: &Path, args: &GenArgs) -> Result<()> {
     }
 }
 
+/// Serves each TCP connection in turn, like the named pipe daemon.
+fn run_tcp_daemon(addr: &str, args: &GenArgs) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| Error::destination(format!("tcp-listen://{addr}"), e))?;
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => serve_reader(args, stream)?,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {e}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Streams to a reader of the daemon until it disconnects, which ends the stream as usual.
+/// Any other error, like invalid arguments, would fail for every reader, so it stops the daemon.
+fn serve_reader<T: Write + Splice + Send + 'static>(args: &GenArgs, writer: T) -> Result<()> {
+    match run(args, writer) {
+        Err(e) if e.is_disconnect() => Ok(()),
+        result => result,
+    }
+}
+
 pub enum DaemonWriter {
     #[cfg(unix)]
-    Unix(PathBuf),
+    Unix {
+        path: PathBuf,
+        fifo: Option<fs::File>,
+    },
     #[cfg(windows)]
     Windows(NamedPipe),
 }
 
 impl DaemonWriter {
-    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
         let path = path.as_ref().to_path_buf();
 
-        if path.exists() {
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                "Named pipe already exists",
-            ));
-        }
-
         #[cfg(unix)]
         {
-            todo!("Implement Unix named pipe creation");
+            use std::{ffi::CString, os::unix::ffi::OsStrExt, os::unix::fs::FileTypeExt};
+
+            // The named pipe outlives each connection, so reuse it if it's already there
+            if path.exists() {
+                if !fs::metadata(&path)?.file_type().is_fifo() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        "File already exists and is not a named pipe",
+                    ));
+                }
+            } else {
+                let c_path = CString::new(path.as_os_str().as_bytes())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(DaemonWriter::Unix { path, fifo: None })
         }
 
         #[cfg(windows)]
         {
+            if path.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "Named pipe already exists",
+                ));
+            }
+
             Ok(DaemonWriter::Windows(
                 NamedPipeBuilder::new(path)
                     .inbound(false)
@@ -96,11 +399,13 @@ impl DaemonWriter {
         }
     }
 
-    pub fn connect(&self) -> Result<()> {
+    pub fn connect(&mut self) -> io::Result<()> {
         match self {
             #[cfg(unix)]
-            DaemonWriter::Unix(path) => {
-                todo!("Implement Unix named pipe connection");
+            DaemonWriter::Unix { path, fifo } => {
+                // Opening a named pipe for writing blocks until a reader opens it
+                *fifo = Some(fs::OpenOptions::new().write(true).open(path)?);
+                Ok(())
             }
             #[cfg(windows)]
             DaemonWriter::Windows(pipe) => pipe.connect(),
@@ -108,13 +413,23 @@ impl DaemonWriter {
     }
 }
 
+impl Splice for DaemonWriter {
+    #[cfg(target_os = "linux")]
+    fn pipe(&self) -> Option<std::os::unix::io::RawFd> {
+        match self {
+            DaemonWriter::Unix { fifo, .. } => fifo.as_ref().and_then(|fifo| fifo.pipe()),
+        }
+    }
+}
+
 impl Write for DaemonWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match self {
             #[cfg(unix)]
-            DaemonWriter::Unix(_) => {
-                todo!("Implement Unix named pipe write");
-            }
+            DaemonWriter::Unix { fifo, .. } => fifo
+                .as_mut()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?
+                .write(buf),
             #[cfg(windows)]
             DaemonWriter::Windows(pipe) => pipe.write(buf),
         }
@@ -122,9 +437,10 @@ impl Write for DaemonWriter {
     fn flush(&mut self) -> io::Result<()> {
         match self {
             #[cfg(unix)]
-            DaemonWriter::Unix(_) => {
-                todo!("Implement Unix named pipe flush");
-            }
+            DaemonWriter::Unix { fifo, .. } => match fifo {
+                Some(fifo) => fifo.flush(),
+                None => Ok(()),
+            },
             #[cfg(windows)]
             DaemonWriter::Windows(pipe) => pipe.flush(),
         }
@@ -0,0 +1,394 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::args::{Endianness, UnicodeEncoding};
+
+/// A kind of invalid sequence which can be injected into encoded output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformation {
+    /// A character encoded with more bytes than necessary (UTF-8).
+    Overlong,
+    /// A surrogate code point which is not part of a pair (UTF-8, UTF-16 and UTF-32).
+    LoneSurrogate,
+    /// A multi-byte sequence cut short by an ASCII character (UTF-8).
+    Truncated,
+    /// A continuation byte without a leading byte (UTF-8).
+    StrayContinuation,
+    /// A value above U+10FFFF (UTF-32).
+    OutOfRange,
+}
+
+impl Malformation {
+    fn kinds(encoding: &UnicodeEncoding) -> &'static [Malformation] {
+        match encoding {
+            UnicodeEncoding::Utf8 => &[
+                Malformation::Overlong,
+                Malformation::LoneSurrogate,
+                Malformation::Truncated,
+                Malformation::StrayContinuation,
+            ],
+            UnicodeEncoding::Utf16 => &[Malformation::LoneSurrogate],
+            UnicodeEncoding::Utf32 => &[Malformation::LoneSurrogate, Malformation::OutOfRange],
+        }
+    }
+
+    /// The numbers of bytes this malformation can take up in the encoding.
+    fn lens(&self, encoding: &UnicodeEncoding) -> &'static [usize] {
+        match (self, encoding) {
+            (Malformation::StrayContinuation, _) => &[1],
+            (Malformation::Overlong | Malformation::Truncated, _) => &[2, 3, 4],
+            (Malformation::LoneSurrogate, UnicodeEncoding::Utf8) => &[3],
+            // A low surrogate, or a high surrogate followed by an ASCII character
+            (Malformation::LoneSurrogate, UnicodeEncoding::Utf16) => &[2, 4],
+            (_, _) => &[4],
+        }
+    }
+}
+
+impl Display for Malformation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Malformation::Overlong => write!(f, "overlong"),
+            Malformation::LoneSurrogate => write!(f, "lone surrogate"),
+            Malformation::Truncated => write!(f, "truncated"),
+            Malformation::StrayContinuation => write!(f, "stray continuation"),
+            Malformation::OutOfRange => write!(f, "out of range"),
+        }
+    }
+}
+
+/// Injects malformed sequences at a given rate and keeps count of them across threads.
+#[derive(Debug)]
+pub struct Malformed {
+    rate: f64,
+    encoding: UnicodeEncoding,
+    endian: Endianness,
+    counts: [AtomicUsize; 5],
+    bytes: AtomicUsize,
+}
+
+impl Malformed {
+    pub fn new(rate: f64, encoding: UnicodeEncoding, endian: Endianness) -> Self {
+        Malformed {
+            rate,
+            encoding,
+            endian,
+            counts: Default::default(),
+            bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Decides whether the next character should be malformed,
+    /// and if so writes a malformed sequence of a length which `fits`.
+    /// Returns the number of bytes written.
+    pub fn inject(
+        &self,
+        fits: impl Fn(usize) -> bool,
+        rng: &mut impl Rng,
+        buffer: &mut Vec<u8>,
+    ) -> usize {
+        if !rng.gen_bool(self.rate) {
+            return 0;
+        }
+
+        let kinds: Vec<(Malformation, Vec<usize>)> = Malformation::kinds(&self.encoding)
+            .iter()
+            .map(|kind| {
+                let lens = kind.lens(&self.encoding);
+                (
+                    *kind,
+                    lens.iter().copied().filter(|len| fits(*len)).collect(),
+                )
+            })
+            .filter(|(_, lens): &(_, Vec<usize>)| !lens.is_empty())
+            .collect();
+
+        let Some((kind, lens)) = kinds.choose(rng) else {
+            return 0;
+        };
+
+        let kind = *kind;
+        let len = *lens.choose(rng).expect("Only kinds with lengths are left");
+
+        let start = buffer.len();
+
+        match self.encoding {
+            UnicodeEncoding::Utf8 => utf8(kind, len, rng, buffer),
+            UnicodeEncoding::Utf16 => {
+                // A high surrogate is followed by an ASCII character
+                // so that it can't pair up with a following low surrogate.
+                let units: &[u16] = if len == 4 {
+                    &[rng.gen_range(0xD800..=0xDBFF), rng.gen_range(0x20..0x7F)]
+                } else {
+                    &[rng.gen_range(0xDC00..=0xDFFF)]
+                };
+
+                for unit in units {
+                    buffer.extend_from_slice(&match self.endian {
+                        Endianness::Little => unit.to_le_bytes(),
+                        Endianness::Big => unit.to_be_bytes(),
+                    });
+                }
+            }
+            UnicodeEncoding::Utf32 => {
+                let value: u32 = match kind {
+                    Malformation::OutOfRange => rng.gen_range(0x110000..=u32::MAX),
+                    _ => rng.gen_range(0xD800..=0xDFFF),
+                };
+
+                buffer.extend_from_slice(&match self.endian {
+                    Endianness::Little => value.to_le_bytes(),
+                    Endianness::Big => value.to_be_bytes(),
+                });
+            }
+        }
+
+        let len = buffer.len() - start;
+        self.counts[kind as usize].fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(len, Ordering::Relaxed);
+        len
+    }
+
+    pub fn summary(&self) -> String {
+        let total: usize = self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+        let bytes = self.bytes.load(Ordering::Relaxed);
+
+        let mut summary = format!("Injected {total} malformed sequences ({bytes} bytes)");
+
+        for kind in Malformation::kinds(&self.encoding) {
+            let count = self.counts[*kind as usize].load(Ordering::Relaxed);
+            summary.push_str(&format!("\n  {kind}: {count}"));
+        }
+
+        summary
+    }
+}
+
+fn utf8(kind: Malformation, len: usize, rng: &mut impl Rng, buffer: &mut Vec<u8>) {
+    match kind {
+        Malformation::StrayContinuation => buffer.push(rng.gen_range(0x80..=0xBF)),
+        Malformation::LoneSurrogate => {
+            // WTF-8 encoding of a surrogate code point
+            buffer.extend_from_slice(&[
+                0xED,
+                rng.gen_range(0xA0..=0xBF),
+                rng.gen_range(0x80..=0xBF),
+            ]);
+        }
+        Malformation::Overlong => {
+            // Encode an ASCII character with 2, 3 or 4 bytes
+            let ascii: u8 = rng.gen_range(0x20..0x7F);
+
+            match len {
+                2 => buffer.extend_from_slice(&[0xC0 | (ascii >> 6), 0x80 | (ascii & 0x3F)]),
+                3 => buffer.extend_from_slice(&[0xE0, 0x80 | (ascii >> 6), 0x80 | (ascii & 0x3F)]),
+                _ => buffer.extend_from_slice(&[
+                    0xF0,
+                    0x80,
+                    0x80 | (ascii >> 6),
+                    0x80 | (ascii & 0x3F),
+                ]),
+            }
+        }
+        Malformation::Truncated => {
+            // A leading byte for a sequence of `len` bytes with one continuation byte missing,
+            // followed by an ASCII character so that it can't be completed by what comes next.
+            let lead = match len {
+                2 => rng.gen_range(0xC2..=0xDF),
+                3 => rng.gen_range(0xE1..=0xEC),
+                _ => rng.gen_range(0xF1..=0xF3),
+            };
+
+            buffer.push(lead);
+
+            for _ in 0..len - 2 {
+                buffer.push(rng.gen_range(0x80..=0xBF));
+            }
+
+            buffer.push(rng.gen_range(0x20..0x7F));
+        }
+        Malformation::OutOfRange => unreachable!("Out of range values only exist in UTF-32"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Malformation, Malformed};
+    use crate::args::{Endianness, UnicodeEncoding};
+
+    const INJECTIONS: usize = 1000;
+
+    /// Injects a malformed sequence each time and returns them.
+    fn inject(malformed: &Malformed) -> Vec<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+
+        (0..INJECTIONS)
+            .map(|_| {
+                let mut buffer = b"ok".to_vec();
+                let len = malformed.inject(|_| true, &mut rng, &mut buffer);
+                assert_eq!(len, buffer.len() - 2);
+                buffer.split_off(2)
+            })
+            .collect()
+    }
+
+    fn utf8_kind(sequence: &[u8]) -> Malformation {
+        assert!(std::str::from_utf8(sequence).is_err(), "{sequence:x?}");
+
+        match *sequence {
+            [0x80..=0xBF] => Malformation::StrayContinuation,
+            [0xED, 0xA0..=0xBF, 0x80..=0xBF] => Malformation::LoneSurrogate,
+            [0xC0 | 0xC1, 0x80..=0xBF]
+            | [0xE0, 0x80..=0x9F, 0x80..=0xBF]
+            | [0xF0, 0x80..=0x8F, 0x80..=0xBF, 0x80..=0xBF] => Malformation::Overlong,
+            [.., last] if last.is_ascii() => {
+                // Everything before the ASCII character is the start of a valid sequence
+                let error = std::str::from_utf8(&sequence[..sequence.len() - 1]).unwrap_err();
+                assert_eq!(error.error_len(), None, "{sequence:x?}");
+                Malformation::Truncated
+            }
+            _ => panic!("Unexpected sequence {sequence:x?}"),
+        }
+    }
+
+    fn utf16_kind(sequence: &[u8], endian: Endianness) -> Malformation {
+        let units: Vec<u16> = sequence
+            .chunks(2)
+            .map(|unit| match endian {
+                Endianness::Little => u16::from_le_bytes([unit[0], unit[1]]),
+                Endianness::Big => u16::from_be_bytes([unit[0], unit[1]]),
+            })
+            .collect();
+
+        assert!(char::decode_utf16(units.iter().copied()).any(|c| c.is_err()));
+
+        match *units {
+            [0xDC00..=0xDFFF] | [0xD800..=0xDBFF, 0x20..=0x7E] => Malformation::LoneSurrogate,
+            _ => panic!("Unexpected units {units:x?}"),
+        }
+    }
+
+    fn utf32_kind(sequence: &[u8], endian: Endianness) -> Malformation {
+        let bytes = sequence.try_into().expect("UTF-32 sequences are 4 bytes");
+        let value = match endian {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        };
+
+        assert_eq!(char::from_u32(value), None);
+
+        match value {
+            0xD800..=0xDFFF => Malformation::LoneSurrogate,
+            0x110000.. => Malformation::OutOfRange,
+            _ => panic!("Unexpected value {value:x}"),
+        }
+    }
+
+    /// Checks that every kind of the encoding was injected and counted.
+    fn check(encoding: UnicodeEncoding, endian: Endianness, kinds: &[Malformation]) {
+        let malformed = Malformed::new(1.0, encoding, endian);
+        let sequences = inject(&malformed);
+
+        let mut counts = [0; 5];
+
+        for sequence in &sequences {
+            let kind = match malformed.encoding {
+                UnicodeEncoding::Utf8 => utf8_kind(sequence),
+                UnicodeEncoding::Utf16 => utf16_kind(sequence, endian),
+                UnicodeEncoding::Utf32 => utf32_kind(sequence, endian),
+            };
+
+            counts[kind as usize] += 1;
+        }
+
+        let bytes: usize = sequences.iter().map(Vec::len).sum();
+        let mut summary = format!("Injected {INJECTIONS} malformed sequences ({bytes} bytes)");
+
+        for kind in kinds {
+            assert!(counts[*kind as usize] > 0, "{kind} wasn't injected");
+            summary.push_str(&format!("\n  {kind}: {}", counts[*kind as usize]));
+        }
+
+        assert_eq!(malformed.summary(), summary);
+    }
+
+    #[test]
+    fn utf8_sequences() {
+        check(
+            UnicodeEncoding::Utf8,
+            Endianness::Little,
+            &[
+                Malformation::Overlong,
+                Malformation::LoneSurrogate,
+                Malformation::Truncated,
+                Malformation::StrayContinuation,
+            ],
+        );
+    }
+
+    #[test]
+    fn utf16_and_utf32_sequences() {
+        for endian in [Endianness::Little, Endianness::Big] {
+            check(
+                UnicodeEncoding::Utf16,
+                endian,
+                &[Malformation::LoneSurrogate],
+            );
+            check(
+                UnicodeEncoding::Utf32,
+                endian,
+                &[Malformation::LoneSurrogate, Malformation::OutOfRange],
+            );
+        }
+    }
+
+    #[test]
+    fn lengths_which_fit() {
+        let malformed = Malformed::new(1.0, UnicodeEncoding::Utf8, Endianness::Little);
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let mut buffer = vec![];
+            assert_eq!(malformed.inject(|len| len == 1, &mut rng, &mut buffer), 1);
+            assert_eq!(utf8_kind(&buffer), Malformation::StrayContinuation);
+        }
+
+        assert_eq!(malformed.inject(|_| false, &mut rng, &mut vec![]), 0);
+    }
+
+    #[test]
+    fn zero_rate_leaves_output_valid() {
+        let text = "héllo wörld 𐀀";
+        let mut rng = rand::thread_rng();
+
+        for (encoding, valid) in [
+            (UnicodeEncoding::Utf8, text.as_bytes().to_vec()),
+            (
+                UnicodeEncoding::Utf16,
+                text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            ),
+            (
+                UnicodeEncoding::Utf32,
+                text.chars()
+                    .flat_map(|c| (c as u32).to_le_bytes())
+                    .collect(),
+            ),
+        ] {
+            let malformed = Malformed::new(0.0, encoding, Endianness::Little);
+            let mut buffer = valid.clone();
+
+            for _ in 0..INJECTIONS {
+                assert_eq!(malformed.inject(|_| true, &mut rng, &mut buffer), 0);
+            }
+
+            assert_eq!(buffer, valid);
+            assert!(malformed
+                .summary()
+                .starts_with("Injected 0 malformed sequences (0 bytes)"));
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use std::{
+    io::{self, IoSlice, Result, Write},
+    mem,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{positional::Region, splice::Splice};
+
+/// How many buffers can be waiting to be written at once.
+const QUEUE_SIZE: usize = 64;
+
+/// How many buffers each generator thread can have, including the one it fills.
+const BUFFERS_PER_OUTPUT: usize = 4;
+
+/// The most buffers to write with a single vectored write.
+pub const MAX_BATCH: usize = 32;
+
+/// How often a generator thread which is waiting for a buffer checks whether the writer has stopped.
+const WRITER_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A filled buffer and where to return it once it has been written.
+pub type Job = (Vec<u8>, Sender<Vec<u8>>);
+
+/// Writes the buffers of all generator threads on a dedicated thread.
+///
+/// The generator threads hand over filled buffers through a bounded channel and get
+/// empty ones back once they have been written, so they neither wait on each other
+/// for the writer nor allocate a new buffer for every write.
+/// When the writer is a pipe on Linux, the buffers are spliced into it instead of copied.
+pub struct Pipeline {
+    jobs: SyncSender<Job>,
+    error: Arc<Mutex<Option<io::Error>>>,
+    writer: JoinHandle<()>,
+}
+
+impl Pipeline {
+    pub fn new<T: Write + Splice + Send + 'static>(writer: Arc<Mutex<T>>) -> Self {
+        let (jobs, job_receiver) = mpsc::sync_channel::<Job>(QUEUE_SIZE);
+        let error = Arc::new(Mutex::new(None));
+
+        #[cfg(target_os = "linux")]
+        let pipe = writer.lock().expect("Failed to lock writer").pipe();
+
+        let writer = {
+            let error = Arc::clone(&error);
+
+            thread::spawn(move || {
+                #[cfg(target_os = "linux")]
+                let result = match pipe {
+                    Some(pipe) => crate::splice::write_jobs(&writer, pipe, &job_receiver),
+                    None => write_jobs(&writer, &job_receiver),
+                };
+
+                #[cfg(not(target_os = "linux"))]
+                let result = write_jobs(&writer, &job_receiver);
+
+                if let Err(e) = result {
+                    *error.lock().expect("Failed to lock writer error") = Some(e);
+                }
+            })
+        };
+
+        Pipeline {
+            jobs,
+            error,
+            writer,
+        }
+    }
+
+    /// An output for a generator thread which writes through the pipeline.
+    pub fn output(&self) -> Output {
+        let (returns, spare) = mpsc::channel();
+
+        Output::Pipeline {
+            jobs: self.jobs.clone(),
+            returns,
+            spare,
+            buffers: 1,
+            error: Arc::clone(&self.error),
+        }
+    }
+
+    /// Waits for everything to be written.
+    /// Every output has to be dropped first.
+    pub fn finish(self) -> Result<()> {
+        drop(self.jobs);
+        self.writer.join().expect("Thread panicked");
+
+        match self
+            .error
+            .lock()
+            .expect("Failed to lock writer error")
+            .take()
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Where a generator thread sends what it generated.
+pub enum Output {
+    Pipeline {
+        jobs: SyncSender<Job>,
+        returns: Sender<Vec<u8>>,
+        spare: Receiver<Vec<u8>>,
+        /// How many buffers this output has allocated so far.
+        buffers: usize,
+        error: Arc<Mutex<Option<io::Error>>>,
+    },
+    /// Writes straight to a part of a file.
+    Region(Region),
+}
+
+impl Output {
+    /// Writes the buffer and leaves an empty buffer in its place.
+    pub fn send(&mut self, buffer: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Output::Pipeline {
+                jobs,
+                returns,
+                spare,
+                buffers,
+                error,
+            } => {
+                let empty = match spare.try_recv() {
+                    Ok(empty) => empty,
+                    Err(TryRecvError::Empty) if *buffers < BUFFERS_PER_OUTPUT => {
+                        *buffers += 1;
+                        Vec::with_capacity(buffer.capacity())
+                    }
+                    // Wait for the writer to catch up.
+                    // This output can always return buffers to itself,
+                    // so check every so often whether the writer has stopped.
+                    _ => loop {
+                        match spare.recv_timeout(WRITER_CHECK_INTERVAL) {
+                            Ok(empty) => break empty,
+                            Err(_) if writer_stopped(error) => return Err(writer_error(error)),
+                            Err(_) => {}
+                        }
+                    },
+                };
+
+                let full = mem::replace(buffer, empty);
+
+                jobs.send((full, returns.clone()))
+                    .map_err(|_| writer_error(error))
+            }
+            Output::Region(region) => {
+                region.write_all(buffer)?;
+                buffer.clear();
+                Ok(())
+            }
+        }
+    }
+}
+
+fn writer_stopped(error: &Mutex<Option<io::Error>>) -> bool {
+    error.lock().expect("Failed to lock writer error").is_some()
+}
+
+/// Why the writer stopped, for the generator threads.
+/// The original error is returned by `Pipeline::finish`.
+fn writer_error(error: &Mutex<Option<io::Error>>) -> io::Error {
+    match &*error.lock().expect("Failed to lock writer error") {
+        Some(e) => io::Error::new(e.kind(), e.to_string()),
+        None => io::ErrorKind::BrokenPipe.into(),
+    }
+}
+
+pub fn write_jobs<T: Write>(writer: &Mutex<T>, jobs: &Receiver<Job>) -> Result<()> {
+    let mut batch = Vec::with_capacity(MAX_BATCH);
+
+    while receive_batch(jobs, &mut batch, None).is_ok() {
+        let mut slices: Vec<IoSlice> = batch
+            .iter()
+            .map(|(buffer, _)| IoSlice::new(buffer))
+            .collect();
+
+        write_all_vectored(
+            &mut *writer.lock().expect("Failed to lock writer"),
+            &mut slices,
+        )?;
+
+        for (buffer, returns) in batch.drain(..) {
+            return_buffer(buffer, &returns);
+        }
+    }
+
+    Ok(())
+}
+
+/// Waits up to `timeout` for a job and takes whatever else is ready
+/// to write it all at once.
+pub fn receive_batch(
+    jobs: &Receiver<Job>,
+    batch: &mut Vec<Job>,
+    timeout: Option<Duration>,
+) -> std::result::Result<(), RecvTimeoutError> {
+    let job = match timeout {
+        Some(timeout) => jobs.recv_timeout(timeout)?,
+        None => jobs.recv().map_err(|_| RecvTimeoutError::Disconnected)?,
+    };
+
+    batch.push(job);
+
+    while batch.len() < MAX_BATCH {
+        match jobs.try_recv() {
+            Ok(job) => batch.push(job),
+            Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Hands a written buffer back to the generator thread it came from.
+pub fn return_buffer(mut buffer: Vec<u8>, returns: &Sender<Vec<u8>>) {
+    buffer.clear();
+
+    // The generator thread has finished
+    let _ = returns.send(buffer);
+}
+
+fn write_all_vectored<T: Write>(writer: &mut T, mut slices: &mut [IoSlice]) -> Result<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut slices, written),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Read, Write},
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::{Pipeline, BUFFERS_PER_OUTPUT};
+    use crate::splice::Splice;
+
+    const OUTPUTS: usize = 3;
+    const BUFFER_SIZE: usize = 64 * 1024;
+    /// Enough buffers per output that each buffer is reused many times.
+    const SENDS: usize = 50 * BUFFERS_PER_OUTPUT;
+
+    struct Memory(Vec<u8>);
+
+    impl Write for Memory {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Splice for Memory {}
+
+    /// Sends buffers filled with their output and sequence number from a few threads.
+    fn send_all(pipeline: &Pipeline) {
+        thread::scope(|scope| {
+            for output_index in 0..OUTPUTS {
+                let mut output = pipeline.output();
+
+                scope.spawn(move || {
+                    let mut buffer = Vec::with_capacity(BUFFER_SIZE);
+
+                    for sequence in 0..SENDS {
+                        buffer.resize(BUFFER_SIZE, (output_index * SENDS + sequence) as u8);
+                        buffer[..2].copy_from_slice(&[output_index as u8, sequence as u8]);
+                        output.send(&mut buffer).unwrap();
+                    }
+                });
+            }
+        });
+    }
+
+    /// Checks that every buffer arrived whole and in order for its output.
+    fn check(data: &[u8]) {
+        assert_eq!(data.len(), OUTPUTS * SENDS * BUFFER_SIZE);
+
+        let mut next = [0; OUTPUTS];
+
+        for buffer in data.chunks(BUFFER_SIZE) {
+            let (output_index, sequence) = (buffer[0] as usize, buffer[1] as usize);
+            let fill = (output_index * SENDS + sequence) as u8;
+
+            assert_eq!(sequence, next[output_index]);
+            assert!(buffer[2..].iter().all(|&b| b == fill));
+            next[output_index] += 1;
+        }
+
+        assert_eq!(next, [SENDS; OUTPUTS]);
+    }
+
+    #[test]
+    fn pipeline_writes_every_buffer() {
+        let writer = Arc::new(Mutex::new(Memory(Vec::new())));
+        let pipeline = Pipeline::new(Arc::clone(&writer));
+
+        send_all(&pipeline);
+        pipeline.finish().unwrap();
+
+        check(&writer.lock().unwrap().0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pipeline_splices_into_pipe() {
+        use std::{fs::File, os::unix::io::FromRawFd};
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        let read = thread::spawn(move || {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            data
+        });
+
+        let writer = Arc::new(Mutex::new(writer));
+        let pipeline = Pipeline::new(Arc::clone(&writer));
+
+        send_all(&pipeline);
+        pipeline.finish().unwrap();
+        drop(writer);
+
+        // A buffer which was reused while the pipe still referred to it would show up here
+        check(&read.join().unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pipeline_splices_after_other_writers() {
+        use std::{fs::File, os::unix::io::FromRawFd, time::Duration};
+
+        const FOREIGN: usize = 60000;
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, mut writer) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        // Something else wrote to the pipe first and nothing has been read yet
+        writer.write_all(&[b'x'; FOREIGN]).unwrap();
+
+        let read = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            data
+        });
+
+        let writer = Arc::new(Mutex::new(writer));
+        let pipeline = Pipeline::new(Arc::clone(&writer));
+
+        send_all(&pipeline);
+        pipeline.finish().unwrap();
+        drop(writer);
+
+        let data = read.join().unwrap();
+        assert!(data[..FOREIGN].iter().all(|&b| b == b'x'));
+        check(&data[FOREIGN..]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pipeline_fails_when_reader_closes() {
+        use std::{fs::File, os::unix::io::FromRawFd};
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (mut reader, writer) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        let read = thread::spawn(move || {
+            let mut data = vec![0; BUFFER_SIZE];
+            reader.read_exact(&mut data).unwrap();
+        });
+
+        let pipeline = Pipeline::new(Arc::new(Mutex::new(writer)));
+        let mut output = pipeline.output();
+        let mut result = Ok(());
+
+        for _ in 0..SENDS {
+            let mut buffer = vec![1; BUFFER_SIZE];
+            result = output.send(&mut buffer);
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        read.join().unwrap();
+        drop(output);
+
+        let error = result.and(pipeline.finish()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
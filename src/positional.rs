@@ -0,0 +1,300 @@
+use std::{
+    fs,
+    io::{self, Result, Write},
+    sync::Arc,
+};
+
+use crate::{args::GenArgs, compress::compression, error::Error, splice::Splice};
+
+/// Zero regions are only left out of sparse files in whole blocks of this size,
+/// the usual file system block size.
+const HOLE_SIZE: usize = 4096;
+
+/// The size of the file when every thread can write its own part of it,
+/// which needs the size to be known up front and the output to be uncompressed.
+pub fn output_size(args: &GenArgs) -> crate::error::Result<Option<usize>> {
+    let size = args
+        .commands
+        .total()
+        .filter(|_| args.commands.is_sized() && args.commands.duration().is_none());
+
+    let compressed = compression(args).is_some();
+
+    if args.sparse || args.preallocate {
+        if size.is_none() {
+            return Err(Error::input(
+                "--sparse and --preallocate only apply to ascii, bytes and unicode with a --size",
+            ));
+        }
+
+        if compressed {
+            return Err(Error::input(
+                "--sparse and --preallocate can't be used with compression",
+            ));
+        }
+    }
+
+    Ok(size.filter(|_| !compressed))
+}
+
+/// A file which generator threads write to at their own offsets,
+/// so that they don't take turns on a shared writer.
+pub struct PositionalFile {
+    file: fs::File,
+    sparse: bool,
+}
+
+impl PositionalFile {
+    pub fn new(file: fs::File, size: usize, sparse: bool, preallocate: bool) -> Result<Self> {
+        if preallocate {
+            allocate(&file, size as u64)?;
+        } else if sparse {
+            // Holes at the end of the file still count towards its size
+            file.set_len(size as u64)?;
+        }
+
+        Ok(PositionalFile { file, sparse })
+    }
+
+    /// A writer for the `len` bytes which start `offset` bytes into the file.
+    pub fn at(self: &Arc<Self>, offset: usize, len: usize) -> Region {
+        Region {
+            file: Arc::clone(self),
+            offset: offset as u64,
+            end: (offset + len) as u64,
+        }
+    }
+}
+
+/// Writes to consecutive positions of a `PositionalFile`.
+pub struct Region {
+    file: Arc<PositionalFile>,
+    offset: u64,
+    /// Writing past this would overwrite the region of another thread.
+    end: u64,
+}
+
+impl Splice for Region {}
+
+impl Write for Region {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.offset + buf.len() as u64 > self.end {
+            return Err(io::Error::other(format!(
+                "Tried to write {} bytes at {} past the end of the region at {}",
+                buf.len(),
+                self.offset,
+                self.end
+            )));
+        }
+
+        if !self.file.sparse {
+            let written = write_at(&self.file.file, buf, self.offset)?;
+            self.offset += written as u64;
+            return Ok(written);
+        }
+
+        // Takes whole blocks at a time, starting with the rest of the current one,
+        // as long as they are all zeros or all have data
+        let first = (HOLE_SIZE - (self.offset % HOLE_SIZE as u64) as usize).min(buf.len());
+        let is_hole = is_zeros(&buf[..first]);
+        let mut len = first;
+
+        while len < buf.len() {
+            let end = (len + HOLE_SIZE).min(buf.len());
+
+            if is_zeros(&buf[len..end]) != is_hole {
+                break;
+            }
+
+            len = end;
+        }
+
+        // The file already has its final size, so holes are zeros
+        let written = if is_hole {
+            len
+        } else {
+            write_at(&self.file.file, &buf[..len], self.offset)?
+        };
+
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn is_zeros(buf: &[u8]) -> bool {
+    buf.iter().all(|b| *b == 0)
+}
+
+#[cfg(unix)]
+fn write_at(file: &fs::File, buf: &[u8], offset: u64) -> Result<usize> {
+    use std::os::unix::fs::FileExt;
+
+    file.write_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn write_at(file: &fs::File, buf: &[u8], offset: u64) -> Result<usize> {
+    use std::os::windows::fs::FileExt;
+
+    file.seek_write(buf, offset)
+}
+
+#[cfg(target_os = "linux")]
+fn allocate(file: &fs::File, size: u64) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, size as libc::off_t) } {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+/// Elsewhere the file is only extended to its final size.
+#[cfg(not(target_os = "linux"))]
+fn allocate(file: &fs::File, size: u64) -> Result<()> {
+    file.set_len(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Write,
+        path::{Path, PathBuf},
+        sync::Arc,
+        thread,
+    };
+
+    use clap::Parser;
+
+    use super::{output_size, PositionalFile, HOLE_SIZE};
+    use crate::args::GenArgs;
+
+    /// A file which is removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("gen-positional-{name}-{}", std::process::id()));
+
+            TempFile(path)
+        }
+
+        fn create(&self) -> fs::File {
+            fs::File::create(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn size(args: &str) -> crate::error::Result<Option<usize>> {
+        output_size(&GenArgs::try_parse_from(args.split_whitespace()).unwrap())
+    }
+
+    #[test]
+    fn output_sizes() {
+        assert_eq!(size("gen -d out ascii -s 1KB").unwrap(), Some(1000));
+        assert_eq!(
+            size("gen -d out --sparse bytes -s 1KiB").unwrap(),
+            Some(1024)
+        );
+
+        // Everything else is written in order
+        assert_eq!(size("gen -d out int -n 100").unwrap(), None);
+        assert_eq!(size("gen -d out ascii --duration 1s").unwrap(), None);
+        assert_eq!(
+            size("gen -d out --compress gzip ascii -s 1KB").unwrap(),
+            None
+        );
+
+        assert!(size("gen -d out --sparse int -n 100").is_err());
+        assert!(size("gen -d out --preallocate ascii").is_err());
+        assert!(size("gen -d out --preallocate --compress zstd bytes -s 1KB").is_err());
+    }
+
+    fn write_regions(path: &Path, data: &[u8], regions: usize, sparse: bool, preallocate: bool) {
+        let file = PositionalFile::new(
+            fs::File::create(path).unwrap(),
+            data.len(),
+            sparse,
+            preallocate,
+        );
+        let file = Arc::new(file.unwrap());
+        let region_size = data.len().div_ceil(regions);
+
+        // Each thread writes its own region in small, unaligned writes
+        thread::scope(|scope| {
+            for (i, part) in data.chunks(region_size).enumerate() {
+                let mut region = file.at(i * region_size, part.len());
+
+                scope.spawn(move || {
+                    for piece in part.chunks(1000) {
+                        region.write_all(piece).unwrap();
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn regions_in_parallel() {
+        let temp = TempFile::new("regions");
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+
+        write_regions(&temp.0, &data, 3, false, false);
+        assert_eq!(fs::read(&temp.0).unwrap(), data);
+    }
+
+    #[test]
+    fn writing_past_the_region() {
+        let temp = TempFile::new("past");
+        let file = Arc::new(PositionalFile::new(temp.create(), 10, false, false).unwrap());
+        let mut region = file.at(0, 8);
+
+        region.write_all(&[1; 8]).unwrap();
+        assert!(region.write_all(&[1]).is_err());
+    }
+
+    #[test]
+    fn sparse_holes() {
+        let temp = TempFile::new("sparse");
+
+        // Zeros at the start, in the middle and at the end, not all of them whole blocks
+        let mut data = vec![0; 10 * HOLE_SIZE];
+        data[HOLE_SIZE * 3 / 2..HOLE_SIZE * 3].fill(7);
+        data[HOLE_SIZE * 6..HOLE_SIZE * 6 + 1].fill(9);
+
+        write_regions(&temp.0, &data, 2, true, false);
+        assert_eq!(fs::read(&temp.0).unwrap(), data);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            // Blocks of 512 bytes, most of the file was never written
+            let allocated = fs::metadata(&temp.0).unwrap().blocks() * 512;
+            assert!(allocated < data.len() as u64 / 2, "{allocated}");
+        }
+    }
+
+    #[test]
+    fn preallocated() {
+        let temp = TempFile::new("preallocated");
+        let file = PositionalFile::new(temp.create(), 50_000, false, true).unwrap();
+
+        assert_eq!(file.file.metadata().unwrap().len(), 50_000);
+
+        let data = vec![3; 50_000];
+        write_regions(&temp.0, &data, 4, false, true);
+        assert_eq!(fs::read(&temp.0).unwrap(), data);
+    }
+}
@@ -1,172 +1,554 @@
 #![allow(clippy::too_many_arguments)]
 
 use std::{
-    io::{Result, Write},
+    fs,
+    io::{self, Write},
     num::NonZeroUsize,
+    path::PathBuf,
     sync::{Arc, Mutex},
-    thread,
+    thread::{self, JoinHandle},
 };
 
-use crate::args::{
-    ByteSize, ByteUnit, Command, FloatRange, GenArgs, IntRange, Size, UnicodeEncoding, UuidVersion,
+use crate::{
+    args::{
+        Bandwidth, ByteSize, ByteUnit, Command, CountRange, Endianness, FloatRange, GenArgs,
+        IntRange, Rate, Ratio, Scheme, Size, UnicodeEncoding, UuidVersion,
+    },
+    charset::Charset,
+    deadline::Deadline,
+    dedupe::{Dedupe, Deduplicator},
+    error::{Error, Result},
+    format::Format,
+    malformed::Malformed,
+    pipeline::{Output, Pipeline},
+    positional::PositionalFile,
+    rate_limit::RateLimiter,
+    regex::Pattern,
+    splice::Splice,
+    template::Template,
+    unicode_tables::{self, Block, GeneralCategory, Script},
+    url::{UrlOptions, TLDS},
 };
 use indicatif::{ProgressBar, ProgressStyle};
-use rand::Rng;
-use random_string::{charsets, generate, generate_rng};
-use uuid::Uuid;
+use rand::{rngs::ThreadRng, Rng};
+use uuid::{Builder, Uuid};
+
+/// Runs the command, writing through `writer`.
+/// For a `positional` file, the threads of the ascii, bytes and unicode
+/// generators each write their own part of it instead.
+pub fn run<T: Write + Splice + Send + 'static>(
+    args: GenArgs,
+    writer: Arc<Mutex<T>>,
+    positional: Option<Arc<PositionalFile>>,
+) -> Result<()> {
+    let pipeline = Pipeline::new(writer);
 
-pub fn run<T: Write + Send + 'static>(args: GenArgs, writer: Arc<Mutex<T>>) {
-    match args.commands {
+    let generated = match args.commands {
         Command::Int {
             range,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
         } => generate_int(
             range,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
-            writer,
+            &pipeline,
             args.daemon,
         ),
         Command::Float {
             range,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
         } => generate_float(
             range,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
-            writer,
+            &pipeline,
             args.daemon,
         ),
         Command::Uuid {
             version,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
         } => generate_uuid(
             version,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
-            writer,
+            &pipeline,
             args.daemon,
         ),
         Command::Url {
             length,
+            scheme,
+            tld,
+            subdomains,
             resource: path,
             query,
+            port,
+            userinfo,
+            fragment,
+            ip,
+            idn,
+            percent_encoded,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
         } => generate_url(
-            length,
-            path,
-            query,
+            UrlOptions {
+                length: length.map(NonZeroUsize::get),
+                schemes: scheme.unwrap_or_else(|| vec![Scheme::Https]),
+                tlds: tld.unwrap_or_else(|| TLDS.iter().map(|tld| tld.to_string()).collect()),
+                subdomains: subdomains.unwrap_or(CountRange { min: 0, max: 0 }),
+                segments: path.map(|p| p.unwrap_or(1)),
+                query: query.map(|q| q.unwrap_or(CountRange { min: 1, max: 3 })),
+                port: port.map_or(0.0, |r| r.value),
+                userinfo: userinfo.map_or(0.0, |r| r.value),
+                fragment: fragment.map_or(0.0, |r| r.value),
+                ip: ip.map_or(0.0, |r| r.value),
+                idn: idn.map_or(0.0, |r| r.value),
+                percent_encoded: percent_encoded.map_or(0.0, |r| r.value),
+            },
+            amount,
+            duration,
+            rate,
+            threads,
+            buf_size,
+            progress,
+            &pipeline,
+            args.daemon,
+        ),
+        Command::Template {
+            template,
+            file,
+            amount,
+            duration,
+            rate,
+            threads,
+            buf_size,
+            progress,
+        } => generate_template(
+            template,
+            file,
+            amount,
+            duration,
+            rate,
+            threads,
+            buf_size,
+            progress,
+            &pipeline,
+            args.daemon,
+        ),
+        Command::Regex {
+            pattern,
+            max_repeat,
+            unicode,
+            amount,
+            duration,
+            rate,
+            threads,
+            buf_size,
+            progress,
+        } => generate_regex(
+            pattern,
+            max_repeat,
+            unicode,
             amount,
+            duration,
+            rate,
             threads,
             buf_size,
             progress,
-            writer,
+            &pipeline,
             args.daemon,
         ),
         Command::Ascii {
             size,
+            duration,
             charset,
             printable_only,
             exclude,
             exclude_codes,
+            compressibility,
+            dedupe_ratio,
+            dedupe_block_size,
+            dedupe_distance,
+            bandwidth,
             threads,
             buf_size,
             progress,
         } => generate_ascii(
             size,
-            charset,
-            printable_only,
-            exclude,
-            exclude_codes,
+            duration,
+            AsciiContent::new(
+                charset,
+                printable_only,
+                exclude,
+                exclude_codes,
+                compressibility,
+            )?,
+            dedupe_ratio
+                .map(|ratio| Dedupe::new(ratio, dedupe_block_size, dedupe_distance))
+                .transpose()?,
+            bandwidth,
+            threads,
+            buf_size,
+            progress,
+            &pipeline,
+            positional,
+            args.daemon,
+        ),
+        Command::Bytes {
+            size,
+            duration,
+            compressibility,
+            dedupe_ratio,
+            dedupe_block_size,
+            dedupe_distance,
+            bandwidth,
+            threads,
+            buf_size,
+            progress,
+        } => generate_bytes(
+            size,
+            duration,
+            BytesContent::new(compressibility),
+            dedupe_ratio
+                .map(|ratio| Dedupe::new(ratio, dedupe_block_size, dedupe_distance))
+                .transpose()?,
+            bandwidth,
             threads,
             buf_size,
             progress,
-            writer,
+            &pipeline,
+            positional,
             args.daemon,
         ),
         Command::Unicode {
             size,
+            duration,
             encoding,
             charset,
             exclude,
+            script,
+            block,
+            category,
+            exclude_category,
+            assigned_only,
+            endian,
+            bom,
+            malformed,
+            bandwidth,
             threads,
             buf_size,
             progress,
         } => generate_unicode(
             size,
-            encoding,
-            charset,
-            exclude,
+            duration,
+            UnicodeContent::new(
+                encoding,
+                charset,
+                exclude,
+                script,
+                block,
+                category,
+                exclude_category,
+                assigned_only,
+                endian,
+                bom,
+                malformed,
+            )?,
+            bandwidth,
             threads,
             buf_size,
             progress,
-            writer,
+            &pipeline,
+            positional,
             args.daemon,
         ),
-    }
+        Command::Tree { .. } | Command::Serve { .. } => {
+            unreachable!("The tree and serve commands are handled in main")
+        }
+    };
+
+    // Whatever was generated is still written,
+    // and the writer has the original error if writing failed
+    pipeline.finish()?;
+    generated
 }
 
 const SIMUL_BYTES: usize = 8;
 
-fn generate_int<T: Write + Send + 'static>(
+/// How many values each thread generates per write unless --buf-size says otherwise.
+const VALUES_PER_WRITE: usize = 16 * 1024;
+
+/// How many bytes each thread generates per write unless --buf-size says otherwise,
+/// rounded down to whole units of the generator.
+const BYTES_PER_WRITE: usize = 1024 * 1024;
+
+fn generate_int(
     range: Option<IntRange>,
     amount: Option<Size>,
+    duration: Option<humantime::Duration>,
+    rate: Option<Rate>,
     threads: Option<NonZeroUsize>,
     buf_size: Option<Size>,
     progress: bool,
-    writer: Arc<Mutex<T>>,
+    pipeline: &Pipeline,
     daemon: bool,
-) {
+) -> Result<()> {
     let min = range.clone().map_or(0, |r| r.min);
     let max = range.map_or(99, |r| r.max);
 
-    let infinite = daemon && amount.is_none();
+    generate_values(
+        move |rng| Ok(rng.gen_range(min..=max)),
+        "integers",
+        amount,
+        duration,
+        rate,
+        threads,
+        buf_size,
+        progress,
+        pipeline,
+        daemon,
+    )
+}
+
+fn generate_float(
+    range: Option<FloatRange>,
+    amount: Option<Size>,
+    duration: Option<humantime::Duration>,
+    rate: Option<Rate>,
+    threads: Option<NonZeroUsize>,
+    buf_size: Option<Size>,
+    progress: bool,
+    pipeline: &Pipeline,
+    daemon: bool,
+) -> Result<()> {
+    let min = range.clone().map_or(0.0, |r| r.min);
+    let max = range.map_or(1.0, |r| r.max);
+
+    generate_values(
+        move |rng| Ok(rng.gen_range(min..=max)),
+        "floats",
+        amount,
+        duration,
+        rate,
+        threads,
+        buf_size,
+        progress,
+        pipeline,
+        daemon,
+    )
+}
+
+fn generate_uuid(
+    version: Option<UuidVersion>,
+    amount: Option<Size>,
+    duration: Option<humantime::Duration>,
+    rate: Option<Rate>,
+    threads: Option<NonZeroUsize>,
+    buf_size: Option<Size>,
+    progress: bool,
+    pipeline: &Pipeline,
+    daemon: bool,
+) -> Result<()> {
+    let uuid: fn(&mut ThreadRng) -> Uuid = match version {
+        Some(UuidVersion::Empty) => |_| Uuid::nil(),
+        Some(UuidVersion::Max) => |_| Uuid::max(),
+        // The same as Uuid::new_v4, but with the thread's generator instead of the OS
+        Some(UuidVersion::V4) | None => |rng| Builder::from_random_bytes(rng.gen()).into_uuid(),
+        Some(UuidVersion::V7) => |_| Uuid::now_v7(),
+    };
+
+    generate_values(
+        move |rng| Ok(uuid(rng)),
+        "uuids",
+        amount,
+        duration,
+        rate,
+        threads,
+        buf_size,
+        progress,
+        pipeline,
+        daemon,
+    )
+}
+
+fn generate_url(
+    options: UrlOptions,
+    amount: Option<Size>,
+    duration: Option<humantime::Duration>,
+    rate: Option<Rate>,
+    threads: Option<NonZeroUsize>,
+    buf_size: Option<Size>,
+    progress: bool,
+    pipeline: &Pipeline,
+    daemon: bool,
+) -> Result<()> {
+    if options.tlds.iter().any(|tld| tld.is_empty()) {
+        return Err(Error::input("--tld can't be empty"));
+    }
+
+    generate_values(
+        move |rng| Ok(options.generate(rng)),
+        "urls",
+        amount,
+        duration,
+        rate,
+        threads,
+        buf_size,
+        progress,
+        pipeline,
+        daemon,
+    )
+}
+
+fn generate_template(
+    template: Option<Template>,
+    file: Option<PathBuf>,
+    amount: Option<Size>,
+    duration: Option<humantime::Duration>,
+    rate: Option<Rate>,
+    threads: Option<NonZeroUsize>,
+    buf_size: Option<Size>,
+    progress: bool,
+    pipeline: &Pipeline,
+    daemon: bool,
+) -> Result<()> {
+    let template = match (template, file) {
+        (Some(template), _) => template,
+        (None, Some(file)) => {
+            let text = fs::read_to_string(&file)
+                .map_err(|e| Error::input(format!("{}: {e}", file.display())))?;
+
+            let text = text.strip_suffix('\n').unwrap_or(&text);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+
+            text.parse::<Template>()
+                .map_err(|e| Error::input(format!("{}: {e}", file.display())))?
+        }
+        (None, None) => unreachable!("Either a template or a file is required"),
+    };
+
+    generate_values(
+        move |rng| Ok(template.render(rng)),
+        "lines",
+        amount,
+        duration,
+        rate,
+        threads,
+        buf_size,
+        progress,
+        pipeline,
+        daemon,
+    )
+}
+
+fn generate_regex(
+    pattern: Pattern,
+    max_repeat: Option<u32>,
+    unicode: bool,
+    amount: Option<Size>,
+    duration: Option<humantime::Duration>,
+    rate: Option<Rate>,
+    threads: Option<NonZeroUsize>,
+    buf_size: Option<Size>,
+    progress: bool,
+    pipeline: &Pipeline,
+    daemon: bool,
+) -> Result<()> {
+    let generator = pattern
+        .generator(max_repeat.unwrap_or(8), unicode)
+        .ok_or_else(|| Error::input("The pattern doesn't match anything"))?;
+
+    generate_values(
+        move |rng| generator.generate(rng),
+        "strings",
+        amount,
+        duration,
+        rate,
+        threads,
+        buf_size,
+        progress,
+        pipeline,
+        daemon,
+    )
+}
+
+/// Writes `amount` values made by `value`, one per line, split between the threads.
+/// Stops at the first value which can't be made.
+/// Generates until the deadline instead with a --duration, or forever as a daemon.
+/// `name` is what the values are called when reporting how many were generated.
+fn generate_values<T: Format>(
+    value: impl Fn(&mut ThreadRng) -> Result<T> + Send + Sync + 'static,
+    name: &str,
+    amount: Option<Size>,
+    duration: Option<humantime::Duration>,
+    rate: Option<Rate>,
+    threads: Option<NonZeroUsize>,
+    buf_size: Option<Size>,
+    progress: bool,
+    pipeline: &Pipeline,
+    daemon: bool,
+) -> Result<()> {
+    let value = Arc::new(value);
+
+    let infinite = (daemon && amount.is_none()) || duration.is_some();
+    let deadline = duration.map(|d| Arc::new(Deadline::new(*d)));
     let amount = amount.map_or(1, |a| a.get());
     let num_threads = threads.map(|t| t.get()).unwrap_or_else(num_cpus::get);
-    let total_size = amount;
-    let full_chunks = total_size / num_threads;
-    let remaining_bytes = total_size % num_threads;
+    let limiter = rate.map(|r| Arc::new(RateLimiter::new(r.per_second)));
 
-    let mut chunks = vec![full_chunks; num_threads];
+    // Keep the writes small enough for the rate limit to even them out
+    let max_buf_size = limiter
+        .as_ref()
+        .map_or(usize::MAX, |l| l.chunk_size(num_threads, 1));
+    let full_chunks = amount / num_threads;
+    let remaining = amount % num_threads;
 
-    if remaining_bytes > 0 {
-        let additional_chunks = remaining_bytes;
+    let mut chunks = vec![full_chunks; num_threads];
 
-        for i in 0..additional_chunks {
-            chunks[i % num_threads] += 1;
-        }
+    for chunk in chunks.iter_mut().take(remaining) {
+        *chunk += 1;
     }
 
     // Remove one from the first chunk to account
     // for the last write without a newline
     chunks[0] -= 1;
 
-    let progress_bar = Arc::new(if progress && !daemon {
+    let progress_bar = Arc::new(if progress && !infinite {
         Some(create_progress_bar_amount(amount as u64))
     } else {
         None
     });
 
-    let buf_size = Arc::new(buf_size);
-
     let mut handles = vec![];
 
     for chunk_size in chunks {
@@ -174,32 +556,45 @@ fn generate_int<T: Write + Send + 'static>(
             continue;
         }
 
-        let writer = Arc::clone(&writer);
+        let mut output = pipeline.output();
         let progress_bar = Arc::clone(&progress_bar);
+        let limiter = limiter.clone();
+        let deadline = deadline.clone();
+        let value = Arc::clone(&value);
 
-        let buf_size = buf_size.map(|b| b.get()).unwrap_or(if chunk_size > 1000 {
-            chunk_size / num_threads
-        } else {
-            chunk_size
-        });
+        let buf_size = buf_size
+            .map(|b| b.get())
+            .unwrap_or(chunk_size.min(VALUES_PER_WRITE))
+            .min(max_buf_size);
 
         let handle = thread::spawn(move || {
             let mut rng = rand::thread_rng();
-            let mut gen_func = || rng.gen_range(min..=max);
+            let mut gen_func = || value(&mut rng);
 
             if infinite {
-                let buf_size = 1024;
+                let buf_size = 1024.min(max_buf_size);
                 let mut buffer = Vec::with_capacity(buf_size);
 
                 loop {
-                    generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
-
-                    if writeln(&writer, &mut buffer).is_err() {
+                    if deadline.as_ref().is_some_and(|d| d.has_passed()) {
                         break;
                     }
+
+                    generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar)?;
+
+                    match writeln(&mut output, &mut buffer, buf_size, &limiter).map_err(Error::from)
+                    {
+                        // The reader stopped reading, which ends the stream
+                        Err(e) if e.is_broken_pipe() => break,
+                        result => result?,
+                    }
+
+                    if let Some(deadline) = &deadline {
+                        deadline.add(buf_size);
+                    }
                 }
 
-                return;
+                return Ok(());
             }
 
             let mut buffer = Vec::with_capacity(buf_size);
@@ -208,219 +603,480 @@ fn generate_int<T: Write + Send + 'static>(
             let remainder = chunk_size % buf_size;
 
             for _ in 0..rounds {
-                generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
-                writeln(&writer, &mut buffer).expect("Failed to write to buffer");
+                generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar)?;
+                writeln(&mut output, &mut buffer, buf_size, &limiter)?;
             }
 
             if remainder == 0 {
-                return;
+                return Ok(());
             }
 
-            generate_random_value(remainder, &mut gen_func, &mut buffer, &progress_bar);
-            writeln(&writer, &mut buffer).expect("Failed to write to buffer");
+            generate_random_value(remainder, &mut gen_func, &mut buffer, &progress_bar)?;
+            writeln(&mut output, &mut buffer, remainder, &limiter)?;
+
+            Ok(())
         });
 
         handles.push(handle);
     }
 
-    for handle in handles {
-        handle.join().expect("Thread panicked");
+    join(handles)?;
+
+    if let Some(deadline) = deadline {
+        deadline.report(name);
     }
 
     if infinite {
-        return;
+        return Ok(());
+    }
+
+    if let Some(limiter) = &limiter {
+        limiter.acquire(1);
     }
 
-    let mut rng = rand::thread_rng();
+    write(
+        pipeline.output(),
+        value(&mut rand::thread_rng())?.to_bytes(),
+    )?;
 
-    write(&writer, rng.gen_range(min..=max).to_string().as_bytes());
+    Ok(())
 }
 
-fn generate_float<T: Write + Send + 'static>(
-    range: Option<FloatRange>,
-    amount: Option<Size>,
+/// The characters the ascii generator picks from and how compressible it makes them,
+/// set up once for all of its threads, or for all files of a tree.
+struct AsciiContent {
+    chars: Arc<Vec<char>>,
+    /// The fraction of each segment which stays random, for --compressibility.
+    random: Option<f64>,
+    fill: u8,
+}
+
+impl AsciiContent {
+    fn new(
+        charset: Option<Charset>,
+        printable_only: bool,
+        exclude: Option<Charset>,
+        exclude_codes: Option<Vec<u8>>,
+        compressibility: Option<Ratio>,
+    ) -> Result<Self> {
+        let ascii_chars: Vec<char> = if printable_only {
+            (32..127).filter_map(std::char::from_u32).collect()
+        } else {
+            (0..128).filter_map(std::char::from_u32).collect()
+        };
+
+        let mut chars: Vec<char> = charset.map_or(ascii_chars, Charset::into_chars);
+
+        // Each character is written as a single byte
+        if let Some(c) = chars.iter().find(|c| !c.is_ascii()) {
+            return Err(Error::input(format!(
+                "The charset has a non-ASCII character {c:?}, use the unicode command for those"
+            )));
+        }
+
+        if let Some(exclude) = exclude {
+            chars.retain(|c| !exclude.contains(*c));
+        }
+
+        if let Some(exclude_codes) = exclude_codes {
+            chars.retain(|c| !exclude_codes.contains(&(*c as u8)));
+        }
+
+        if chars.is_empty() {
+            return Err(Error::input("Charset cannot be empty"));
+        }
+
+        // Random characters from a small charset already compress somewhat,
+        // so fewer of them are needed to reach the target
+        let random = compressibility
+            .map(|c| ((1.0 - c.value) / ((chars.len() as f64).log2() / 8.0)).min(1.0));
+
+        Ok(AsciiContent {
+            fill: chars[0] as u8,
+            chars: Arc::new(chars),
+            random,
+        })
+    }
+
+    /// Appends `bytes` random characters to the buffer.
+    fn generate(
+        &self,
+        bytes: usize,
+        rng: &mut ThreadRng,
+        buffer: &mut Vec<u8>,
+        progress_bar: &Arc<Option<ProgressBar>>,
+    ) {
+        let start = buffer.len();
+        let chars_len = self.chars.len();
+
+        generate_random_ascii_8(
+            bytes / SIMUL_BYTES,
+            rng,
+            chars_len,
+            buffer,
+            &self.chars,
+            progress_bar,
+        );
+
+        generate_random_ascii(
+            bytes % SIMUL_BYTES,
+            rng,
+            chars_len,
+            buffer,
+            &self.chars,
+            progress_bar,
+        );
+
+        make_compressible(&mut buffer[start..], self.random, self.fill);
+    }
+}
+
+/// Every write of the ascii generator is made of whole units of this size,
+/// which are dedupe blocks so that they line up in the output.
+fn ascii_unit(dedupe: Option<Dedupe>) -> Result<usize> {
+    match dedupe {
+        Some(dedupe) if dedupe.block_size % SIMUL_BYTES != 0 => Err(Error::input(format!(
+            "Dedupe block size must be divisible by {SIMUL_BYTES}"
+        ))),
+        Some(dedupe) => Ok(dedupe.block_size),
+        None => Ok(SIMUL_BYTES),
+    }
+}
+
+fn generate_ascii(
+    size: Option<ByteSize>,
+    duration: Option<humantime::Duration>,
+    content: AsciiContent,
+    dedupe: Option<Dedupe>,
+    bandwidth: Option<Bandwidth>,
     threads: Option<NonZeroUsize>,
-    buf_size: Option<Size>,
+    buf_size: Option<ByteSize>,
     progress: bool,
-    writer: Arc<Mutex<T>>,
+    pipeline: &Pipeline,
+    positional: Option<Arc<PositionalFile>>,
     daemon: bool,
-) {
-    let min = range.clone().map_or(0.0, |r| r.min);
-    let max = range.map_or(1.0, |r| r.max);
+) -> Result<()> {
+    let infinite = (daemon && size.is_none()) || duration.is_some();
+    let deadline = duration.map(|d| Arc::new(Deadline::new(*d)));
+
+    let size = size.unwrap_or(ByteSize {
+        bytes: 1,
+        unit: ByteUnit::B,
+    });
 
-    let infinite = daemon && amount.is_none();
-    let amount = amount.map_or(1, |a| a.get());
     let num_threads = threads.map(|t| t.get()).unwrap_or_else(num_cpus::get);
-    let total_size = amount;
-    let full_chunks = total_size / num_threads;
-    let remaining_bytes = total_size % num_threads;
+    let buf_size = buf_size.map(|b| b.to_bytes() / num_threads);
+
+    let unit = ascii_unit(dedupe)?;
+
+    if let Some(ref buf_size) = buf_size {
+        if buf_size < &unit {
+            return Err(Error::input(format!(
+                "Buffer size after being divided by the number of threads ({num_threads}) must be {unit} or greater and divisible by {unit}"
+            )));
+        }
+    }
+
+    let buf_size = buf_size.map(|b| b / unit * unit);
+
+    let limiter = bandwidth.map(|b| Arc::new(RateLimiter::new(b.bytes_per_second)));
+
+    // Keep the writes small enough for the rate limit to even them out
+    let max_buf_size = limiter
+        .as_ref()
+        .map_or(usize::MAX, |l| l.chunk_size(num_threads, unit));
+
+    let total_size = size.to_bytes();
+
+    let full_chunks = total_size / (num_threads * unit);
+
+    let remaining_bytes = total_size % (num_threads * unit);
 
     let mut chunks = vec![full_chunks; num_threads];
 
-    if remaining_bytes > 0 {
-        let additional_chunks = remaining_bytes;
+    if remaining_bytes >= unit {
+        let additional_chunks = remaining_bytes / unit;
 
         for i in 0..additional_chunks {
             chunks[i % num_threads] += 1;
         }
     }
 
-    // Remove one from the first chunk to account
-    // for the last write without a newline
-    chunks[0] -= 1;
-
-    let progress_bar = Arc::new(if progress && !daemon {
-        Some(create_progress_bar_amount(amount as u64))
+    let progress_bar = Arc::new(if progress && !infinite {
+        Some(create_progress_bar(
+            total_size as u64,
+            size.is_binary_unit(),
+        ))
     } else {
         None
     });
 
+    let content = Arc::new(content);
     let buf_size = Arc::new(buf_size);
 
+    // One history for all threads, so that blocks repeat across them
+    let deduplicator = dedupe.map(Deduplicator::new);
+
     let mut handles = vec![];
+    let mut offset = 0;
 
     for chunk_size in chunks {
         if chunk_size == 0 && !infinite {
             continue;
         }
 
-        let writer = Arc::clone(&writer);
+        let byte_count = chunk_size * unit;
+        let mut output = output_at(pipeline, &positional, offset, byte_count);
+        offset += byte_count;
+
         let progress_bar = Arc::clone(&progress_bar);
+        let limiter = limiter.clone();
+        let deadline = deadline.clone();
+        let mut deduplicator = deduplicator.as_ref().map(Deduplicator::share);
 
-        let buf_size = buf_size.map(|b| b.get()).unwrap_or(if chunk_size > 1000 {
-            chunk_size / num_threads
-        } else {
-            chunk_size
-        });
+        let content = Arc::clone(&content);
+        let buf_size = buf_size
+            .unwrap_or((BYTES_PER_WRITE / unit).max(1) * unit)
+            .min(max_buf_size);
 
         let handle = thread::spawn(move || {
             let mut rng = rand::thread_rng();
 
-            let mut gen_func = || rng.gen_range(min..=max);
-
             if infinite {
-                let buf_size = 1024;
+                let buf_size = (SIMUL_BYTES * 128).div_ceil(unit) * unit;
+                let buf_size = buf_size.min(max_buf_size);
                 let mut buffer = Vec::with_capacity(buf_size);
 
                 loop {
-                    generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
-
-                    if writeln(&writer, &mut buffer).is_err() {
+                    if deadline.as_ref().is_some_and(|d| d.has_passed()) {
                         break;
                     }
+
+                    content.generate(buf_size, &mut rng, &mut buffer, &progress_bar);
+                    deduplicate(&mut deduplicator, &mut buffer);
+
+                    let produced = buffer.len();
+
+                    match write_from_buffer(&mut output, &mut buffer, &limiter).map_err(Error::from)
+                    {
+                        // The reader stopped reading, which ends the stream
+                        Err(e) if e.is_broken_pipe() => break,
+                        result => result?,
+                    }
+
+                    if let Some(deadline) = &deadline {
+                        deadline.add(produced);
+                    }
                 }
 
-                return;
+                return Ok(());
             }
 
             let mut buffer = Vec::with_capacity(buf_size);
-
-            let rounds = chunk_size / buf_size;
-            let remainder = chunk_size % buf_size;
+            let rounds = byte_count / buf_size;
+            let remainder = byte_count % buf_size;
 
             for _ in 0..rounds {
-                generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
-                writeln(&writer, &mut buffer).expect("Failed to write to buffer");
+                content.generate(buf_size, &mut rng, &mut buffer, &progress_bar);
+                deduplicate(&mut deduplicator, &mut buffer);
+
+                write_from_buffer(&mut output, &mut buffer, &limiter)?;
             }
 
+            // Whole units like the rounds, so it's never a partial dedupe block
             if remainder == 0 {
-                return;
+                return Ok(());
             }
 
-            generate_random_value(remainder, &mut gen_func, &mut buffer, &progress_bar);
-            writeln(&writer, &mut buffer).expect("Failed to write to buffer");
+            content.generate(remainder, &mut rng, &mut buffer, &progress_bar);
+            deduplicate(&mut deduplicator, &mut buffer);
+
+            write_from_buffer(&mut output, &mut buffer, &limiter)?;
+
+            Ok(())
         });
 
         handles.push(handle);
     }
 
-    for handle in handles {
-        handle.join().expect("Thread panicked");
+    join(handles)?;
+
+    if let Some(deadline) = deadline {
+        deadline.report("bytes");
     }
 
     if infinite {
-        return;
+        return Ok(());
+    }
+
+    let leftover_bytes = remaining_bytes % unit;
+
+    if leftover_bytes > 0 {
+        let mut buffer = Vec::with_capacity(leftover_bytes);
+
+        content.generate(
+            leftover_bytes,
+            &mut rand::thread_rng(),
+            &mut buffer,
+            &progress_bar,
+        );
+
+        let mut output = output_at(
+            pipeline,
+            &positional,
+            total_size - leftover_bytes,
+            leftover_bytes,
+        );
+        write_from_buffer(&mut output, &mut buffer, &limiter)?;
     }
 
-    let mut rng = rand::thread_rng();
+    Ok(())
+}
 
-    write(&writer, rng.gen_range(min..=max).to_string().as_bytes());
+/// How compressible the bytes generator makes its random bytes.
+struct BytesContent {
+    /// The fraction of each segment which stays random, for --compressibility.
+    random: Option<f64>,
 }
 
-fn generate_uuid<T: Write + Send + 'static>(
-    version: Option<UuidVersion>,
-    amount: Option<Size>,
+impl BytesContent {
+    fn new(compressibility: Option<Ratio>) -> Self {
+        BytesContent {
+            random: compressibility.map(|c| 1.0 - c.value),
+        }
+    }
+
+    /// Appends `bytes` random bytes to the buffer.
+    fn generate(
+        &self,
+        bytes: usize,
+        rng: &mut ThreadRng,
+        buffer: &mut Vec<u8>,
+        progress_bar: &Arc<Option<ProgressBar>>,
+    ) {
+        let start = buffer.len();
+
+        generate_random_bytes(bytes, rng, buffer, progress_bar);
+        make_compressible(&mut buffer[start..], self.random, 0);
+    }
+}
+
+fn generate_bytes(
+    size: Option<ByteSize>,
+    duration: Option<humantime::Duration>,
+    content: BytesContent,
+    dedupe: Option<Dedupe>,
+    bandwidth: Option<Bandwidth>,
     threads: Option<NonZeroUsize>,
-    buf_size: Option<Size>,
+    buf_size: Option<ByteSize>,
     progress: bool,
-    writer: Arc<Mutex<T>>,
+    pipeline: &Pipeline,
+    positional: Option<Arc<PositionalFile>>,
     daemon: bool,
-) {
-    let infinite = daemon && amount.is_none();
-    let amount = amount.map_or(1, |a| a.get());
+) -> Result<()> {
+    let infinite = (daemon && size.is_none()) || duration.is_some();
+    let deadline = duration.map(|d| Arc::new(Deadline::new(*d)));
+
+    let size = size.unwrap_or(ByteSize {
+        bytes: 1,
+        unit: ByteUnit::B,
+    });
+
     let num_threads = threads.map(|t| t.get()).unwrap_or_else(num_cpus::get);
-    let total_size = amount;
-    let full_chunks = total_size / num_threads;
-    let remaining_bytes = total_size % num_threads;
+    let buf_size = buf_size.map(|b| b.to_bytes() / num_threads);
 
-    let mut chunks = vec![full_chunks; num_threads];
+    // Every write is made of whole dedupe blocks so that they line up in the output
+    let unit = dedupe.map_or(1, |d| d.block_size);
 
-    if remaining_bytes > 0 {
-        let additional_chunks = remaining_bytes;
+    if buf_size.is_some_and(|b| b < unit) {
+        return Err(Error::input(format!(
+            "Buffer size after being divided by the number of threads ({num_threads}) must be {unit} or greater"
+        )));
+    }
 
-        for i in 0..additional_chunks {
-            chunks[i % num_threads] += 1;
-        }
-    }
+    let buf_size = buf_size.map(|b| b / unit * unit);
 
-    // Remove one from the first chunk to account
-    // for the last write without a newline
-    chunks[0] -= 1;
+    let limiter = bandwidth.map(|b| Arc::new(RateLimiter::new(b.bytes_per_second)));
 
-    let progress_bar = Arc::new(if progress && !daemon {
-        Some(create_progress_bar_amount(amount as u64))
+    // Keep the writes small enough for the rate limit to even them out
+    let max_buf_size = limiter
+        .as_ref()
+        .map_or(usize::MAX, |l| l.chunk_size(num_threads, unit));
+
+    let content = Arc::new(content);
+
+    let total_size = size.to_bytes();
+    let units = total_size / unit;
+    let mut chunks = vec![units / num_threads * unit; num_threads];
+
+    for chunk in chunks.iter_mut().take(units % num_threads) {
+        *chunk += unit;
+    }
+
+    let progress_bar = Arc::new(if progress && !infinite {
+        Some(create_progress_bar(
+            total_size as u64,
+            size.is_binary_unit(),
+        ))
     } else {
         None
     });
 
-    let buf_size = Arc::new(buf_size);
-
-    let mut gen_func = match version {
-        Some(UuidVersion::Empty) => || Uuid::nil().to_string(),
-        Some(UuidVersion::Max) => || Uuid::max().to_string(),
-        Some(UuidVersion::V4) | None => || Uuid::new_v4().to_string(),
-    };
+    // One history for all threads, so that blocks repeat across them
+    let deduplicator = dedupe.map(Deduplicator::new);
 
     let mut handles = vec![];
+    let mut offset = 0;
 
     for chunk_size in chunks {
         if chunk_size == 0 && !infinite {
             continue;
         }
 
-        let writer = Arc::clone(&writer);
+        let mut output = output_at(pipeline, &positional, offset, chunk_size);
+        offset += chunk_size;
+
         let progress_bar = Arc::clone(&progress_bar);
+        let limiter = limiter.clone();
+        let deadline = deadline.clone();
+        let mut deduplicator = deduplicator.as_ref().map(Deduplicator::share);
 
-        let buf_size = buf_size.map(|b| b.get()).unwrap_or(if chunk_size > 1000 {
-            chunk_size / num_threads
-        } else {
-            chunk_size
-        });
+        let content = Arc::clone(&content);
+        let buf_size = buf_size
+            .unwrap_or((BYTES_PER_WRITE / unit).max(1) * unit)
+            .min(max_buf_size);
 
         let handle = thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+
             if infinite {
-                let buf_size = 1024;
+                let buf_size = (64 * 1024_usize).div_ceil(unit) * unit;
+                let buf_size = buf_size.min(max_buf_size);
                 let mut buffer = Vec::with_capacity(buf_size);
 
                 loop {
-                    generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
-
-                    if writeln(&writer, &mut buffer).is_err() {
+                    if deadline.as_ref().is_some_and(|d| d.has_passed()) {
                         break;
                     }
+
+                    content.generate(buf_size, &mut rng, &mut buffer, &progress_bar);
+                    deduplicate(&mut deduplicator, &mut buffer);
+
+                    let produced = buffer.len();
+
+                    match write_from_buffer(&mut output, &mut buffer, &limiter).map_err(Error::from)
+                    {
+                        // The reader stopped reading, which ends the stream
+                        Err(e) if e.is_broken_pipe() => break,
+                        result => result?,
+                    }
+
+                    if let Some(deadline) = &deadline {
+                        deadline.add(produced);
+                    }
                 }
 
-                return;
+                return Ok(());
             }
 
             let mut buffer = Vec::with_capacity(buf_size);
@@ -428,189 +1084,210 @@ fn generate_uuid<T: Write + Send + 'static>(
             let remainder = chunk_size % buf_size;
 
             for _ in 0..rounds {
-                generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
-                writeln(&writer, &mut buffer).expect("Failed to write to buffer");
+                content.generate(buf_size, &mut rng, &mut buffer, &progress_bar);
+                deduplicate(&mut deduplicator, &mut buffer);
+
+                write_from_buffer(&mut output, &mut buffer, &limiter)?;
             }
 
             if remainder == 0 {
-                return;
+                return Ok(());
             }
 
-            generate_random_value(remainder, &mut gen_func, &mut buffer, &progress_bar);
-            writeln(&writer, &mut buffer).expect("Failed to write to buffer");
+            content.generate(remainder, &mut rng, &mut buffer, &progress_bar);
+            deduplicate(&mut deduplicator, &mut buffer);
+
+            write_from_buffer(&mut output, &mut buffer, &limiter)?;
+
+            Ok(())
         });
 
         handles.push(handle);
     }
 
-    for handle in handles {
-        handle.join().expect("Thread panicked");
+    join(handles)?;
+
+    if let Some(deadline) = deadline {
+        deadline.report("bytes");
     }
 
     if infinite {
-        return;
+        return Ok(());
     }
 
-    write(&writer, gen_func().as_bytes());
-}
-
-fn generate_url<T: Write + Send + 'static>(
-    length: Option<usize>,
-    path: Option<Option<u8>>,
-    query: bool,
-    amount: Option<Size>,
-    threads: Option<NonZeroUsize>,
-    buf_size: Option<Size>,
-    progress: bool,
-    writer: Arc<Mutex<T>>,
-    daemon: bool,
-) {
-    let infinite = daemon && amount.is_none();
-    let amount = amount.map_or(1, |a| a.get());
-    let num_threads = threads.map(|t| t.get()).unwrap_or_else(num_cpus::get);
-    let total_size = amount;
-    let full_chunks = total_size / num_threads;
-    let remaining_bytes = total_size % num_threads;
+    // A partial block goes at the very end so that it doesn't move the blocks after it
+    let leftover_bytes = total_size % unit;
 
-    let mut chunks = vec![full_chunks; num_threads];
+    if leftover_bytes > 0 {
+        let mut buffer = Vec::with_capacity(leftover_bytes);
 
-    if remaining_bytes > 0 {
-        let additional_chunks = remaining_bytes;
+        content.generate(
+            leftover_bytes,
+            &mut rand::thread_rng(),
+            &mut buffer,
+            &progress_bar,
+        );
 
-        for i in 0..additional_chunks {
-            chunks[i % num_threads] += 1;
-        }
+        let mut output = output_at(
+            pipeline,
+            &positional,
+            total_size - leftover_bytes,
+            leftover_bytes,
+        );
+        write_from_buffer(&mut output, &mut buffer, &limiter)?;
     }
 
-    // Remove one from the first chunk to account
-    // for the last write without a newline
-    chunks[0] -= 1;
-
-    let progress_bar = Arc::new(if progress && !daemon {
-        Some(create_progress_bar_amount(amount as u64))
-    } else {
-        None
-    });
-
-    let buf_size = Arc::new(buf_size);
-
-    let mut gen_func = move || {
-        let protocol = "https".to_owned();
-
-        let domain = gen_str(length) + "." + &gen_str(Some(3));
-        let paths = if let Some(p) = path {
-            let mut paths = Vec::new();
+    Ok(())
+}
 
-            for _ in 0..(p.unwrap_or(1)) {
-                paths.push(gen_str(length));
-            }
+/// The characters the unicode generator picks from and how it encodes them,
+/// set up once for all of its threads, or for all files of a tree.
+struct UnicodeContent {
+    /// Sorted by code point.
+    chars: Arc<Vec<char>>,
+    lengths: EncodedLengths,
+    encoding: Arc<UnicodeEncoding>,
+    endian: Endianness,
+    /// The byte order mark to start with, if any.
+    bom: Vec<u8>,
+    malformed: Option<Arc<Malformed>>,
+}
 
-            Some(paths)
-        } else {
-            None
-        };
+impl UnicodeContent {
+    fn new(
+        encoding: UnicodeEncoding,
+        charset: Option<Charset>,
+        exclude: Option<Charset>,
+        script: Option<Vec<Script>>,
+        block: Option<Vec<Block>>,
+        category: Option<Vec<GeneralCategory>>,
+        exclude_category: Option<Vec<GeneralCategory>>,
+        assigned_only: bool,
+        endian: Option<Endianness>,
+        bom: bool,
+        malformed: Option<Ratio>,
+    ) -> Result<Self> {
+        let endian = endian.unwrap_or(Endianness::Little);
+
+        let mut chars = charset.map_or_else(
+            || (0..=0x10FFFF).filter_map(std::char::from_u32).collect(),
+            Charset::into_chars,
+        );
 
-        match (paths, query) {
-            (None, false) => {
-                format!("{protocol}://{domain}")
-            }
-            (Some(p), false) => {
-                format!("{}://{}/{}", protocol, domain, p.join("/"))
-            }
-            (None, true) => {
-                format!("{}://{}/?{}", protocol, domain, gen_str(length))
-            }
-            (Some(p), true) => {
-                format!(
-                    "{}://{}/{}?{}",
-                    protocol,
-                    domain,
-                    p.join("/"),
-                    gen_str(length)
-                )
-            }
+        if let Some(scripts) = script {
+            chars.retain(|c| scripts.iter().any(|s| s.contains(*c)));
         }
-    };
 
-    let mut handles = vec![];
-
-    for chunk_size in chunks {
-        if chunk_size == 0 && !infinite {
-            continue;
+        if let Some(blocks) = block {
+            chars.retain(|c| blocks.iter().any(|b| b.contains(*c)));
         }
 
-        let writer = Arc::clone(&writer);
-        let progress_bar = Arc::clone(&progress_bar);
-
-        let buf_size = buf_size.map(|b| b.get()).unwrap_or(if chunk_size > 1000 {
-            chunk_size / num_threads
-        } else {
-            chunk_size
-        });
-
-        let handle = thread::spawn(move || {
-            if infinite {
-                let buf_size = 1024;
-                let mut buffer = Vec::with_capacity(buf_size);
+        if let Some(categories) = category {
+            chars.retain(|c| categories.iter().any(|gc| gc.contains(*c)));
+        }
 
-                loop {
-                    generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
+        if assigned_only {
+            chars.retain(|c| unicode_tables::is_assigned(*c));
+        }
 
-                    if writeln(&writer, &mut buffer).is_err() {
-                        break;
-                    }
-                }
+        if let Some(categories) = exclude_category {
+            chars.retain(|c| !categories.iter().any(|gc| gc.contains(*c)));
+        }
 
-                return;
-            }
+        if let Some(e) = exclude {
+            chars.retain(|c| !e.contains(*c));
+        }
 
-            let mut buffer = Vec::with_capacity(buf_size);
-            let rounds = chunk_size / buf_size;
-            let remainder = chunk_size % buf_size;
+        // Keep the characters ordered by code point so that the ones
+        // which fit at the end of a buffer are always a prefix of the set.
+        chars.sort_unstable();
 
-            for _ in 0..rounds {
-                generate_random_value(buf_size, &mut gen_func, &mut buffer, &progress_bar);
-                writeln(&writer, &mut buffer).expect("Failed to write to buffer");
-            }
+        if chars.is_empty() {
+            return Err(Error::input("Charset cannot be empty"));
+        }
 
-            if remainder == 0 {
-                return;
-            }
+        let bom = if bom {
+            encoding.bom(endian)
+        } else {
+            Vec::new()
+        };
 
-            generate_random_value(remainder, &mut gen_func, &mut buffer, &progress_bar);
-            writeln(&writer, &mut buffer).expect("Failed to write to buffer");
-        });
+        let lengths = match encoding {
+            UnicodeEncoding::Utf8 => EncodedLengths::new(&chars, char::len_utf8),
+            UnicodeEncoding::Utf16 => EncodedLengths::new(&chars, |c| c.len_utf16() * 2),
+            UnicodeEncoding::Utf32 => EncodedLengths::new(&chars, |_| 4),
+        };
 
-        handles.push(handle);
+        let malformed =
+            malformed.map(|rate| Arc::new(Malformed::new(rate.value, encoding.clone(), endian)));
+
+        Ok(UnicodeContent {
+            chars: Arc::new(chars),
+            lengths,
+            encoding: Arc::new(encoding),
+            endian,
+            bom,
+            malformed,
+        })
     }
 
-    for handle in handles {
-        handle.join().expect("Thread panicked");
+    /// The smallest character in the charset decides the granularity of the output.
+    /// For UTF-16 this is a surrogate pair if there are no characters in the BMP,
+    /// and for UTF-8 e.g. 3 bytes if there are only CJK characters.
+    fn min_byte_size(&self) -> usize {
+        self.lengths.min()
     }
 
-    if infinite {
-        return;
-    }
+    /// Appends up to `bytes` bytes of whole characters to the buffer
+    /// and returns how many it appended.
+    /// That's all of them for a multiple of the smallest character.
+    fn generate(
+        &self,
+        bytes: usize,
+        rng: &mut ThreadRng,
+        buffer: &mut Vec<u8>,
+        progress_bar: &Arc<Option<ProgressBar>>,
+    ) -> usize {
+        let mut current_bytes = 0;
+
+        generate_random_unicode(
+            &self.encoding,
+            self.endian,
+            &mut current_bytes,
+            bytes,
+            rng,
+            &self.lengths,
+            &self.chars,
+            buffer,
+            &self.malformed,
+            progress_bar,
+        );
 
-    write(&writer, gen_func().as_bytes());
+        current_bytes
+    }
 }
 
-fn generate_ascii<T: Write + Send + 'static>(
+fn generate_unicode(
     size: Option<ByteSize>,
-    charset: Option<String>,
-    printable_only: bool,
-    exclude: Option<String>,
-    exclude_codes: Option<Vec<u8>>,
+    duration: Option<humantime::Duration>,
+    content: UnicodeContent,
+    bandwidth: Option<Bandwidth>,
     threads: Option<NonZeroUsize>,
     buf_size: Option<ByteSize>,
     progress: bool,
-    writer: Arc<Mutex<T>>,
+    pipeline: &Pipeline,
+    positional: Option<Arc<PositionalFile>>,
     daemon: bool,
-) {
-    let infinite = daemon && size.is_none();
+) -> Result<()> {
+    let infinite = (daemon && size.is_none()) || duration.is_some();
+    let deadline = duration.map(|d| Arc::new(Deadline::new(*d)));
+
+    let bom = content.bom.clone();
+    let encoding = content.encoding.as_ref().clone();
+    let min_byte_size = content.min_byte_size();
 
     let size = size.unwrap_or(ByteSize {
-        value: 1,
+        bytes: bom.len() + min_byte_size,
         unit: ByteUnit::B,
     });
 
@@ -618,348 +1295,412 @@ fn generate_ascii<T: Write + Send + 'static>(
     let buf_size = buf_size.map(|b| b.to_bytes() / num_threads);
 
     if let Some(ref buf_size) = buf_size {
-        if buf_size < &SIMUL_BYTES {
-            panic!(
-                "Buffer size after being divided by the number of threads ({num_threads}) must be {SIMUL_BYTES} or greater and divisible by {SIMUL_BYTES}"
-            );
+        if buf_size < &min_byte_size {
+            return Err(Error::input(format!(
+                "Buffer size after being divided by the number of threads ({num_threads}) must be {min_byte_size} or greater and divisible by {min_byte_size}"
+            )));
         }
     }
 
-    let buf_size = buf_size.map(|b| b / SIMUL_BYTES * SIMUL_BYTES);
+    let buf_size = buf_size.map(|b| b / min_byte_size * min_byte_size);
 
-    let ascii_chars: Vec<char> = if printable_only {
-        (32..127).filter_map(std::char::from_u32).collect()
-    } else {
-        (0..128).filter_map(std::char::from_u32).collect()
-    };
+    let limiter = bandwidth.map(|b| Arc::new(RateLimiter::new(b.bytes_per_second)));
 
-    let mut chars: Vec<char> = charset
-        .unwrap_or_else(|| ascii_chars.iter().collect())
-        .chars()
-        .collect();
+    // Keep the writes small enough for the rate limit to even them out
+    let max_buf_size = limiter
+        .as_ref()
+        .map_or(usize::MAX, |l| l.chunk_size(num_threads, min_byte_size));
 
-    if let Some(exclude) = exclude {
-        chars.retain(|c| !exclude.contains(*c));
-    }
+    let total_size = size.to_bytes();
 
-    if let Some(exclude_codes) = exclude_codes {
-        chars.retain(|c| !exclude_codes.contains(&(*c as u8)));
+    if total_size < bom.len() + min_byte_size {
+        return Err(Error::input(format!(
+            "Size too small for encoding.\nMinimum size for {encoding} encoding is {} bytes",
+            bom.len() + min_byte_size
+        )));
     }
 
-    if chars.is_empty() {
-        panic!("Charset cannot be empty");
-    }
+    // The byte order mark counts towards the requested size
+    let total_size = total_size - bom.len();
 
-    let total_size = size.to_bytes();
+    if !total_size.is_multiple_of(min_byte_size) {
+        if encoding == UnicodeEncoding::Utf16 && min_byte_size == 4 {
+            return Err(Error::input(format!(
+                "Size must be divisible by {min_byte_size} for {encoding} encoding when the charset only contains surrogate pairs"
+            )));
+        }
 
-    let full_chunks = total_size / (num_threads * SIMUL_BYTES);
+        if encoding == UnicodeEncoding::Utf8 && min_byte_size > 1 {
+            return Err(Error::input(format!(
+                "Size must be divisible by {min_byte_size} for {encoding} encoding when the charset has no characters shorter than {min_byte_size} bytes"
+            )));
+        }
 
-    let remaining_bytes = total_size % (num_threads * SIMUL_BYTES);
+        return Err(Error::input(format!(
+            "Size must be divisible by {min_byte_size} for {encoding} encoding"
+        )));
+    }
 
-    let mut chunks = vec![full_chunks; num_threads];
+    let base_size = total_size / (num_threads * min_byte_size);
+    let remainder = (total_size / min_byte_size) % num_threads;
 
-    if remaining_bytes >= SIMUL_BYTES {
-        let additional_chunks = remaining_bytes / SIMUL_BYTES;
+    let mut chunks = vec![base_size * min_byte_size; num_threads];
 
-        for i in 0..additional_chunks {
-            chunks[i % num_threads] += 1;
-        }
+    for chunk in chunks.iter_mut().take(remainder) {
+        *chunk += min_byte_size;
     }
 
-    let progress_bar = Arc::new(if progress {
+    let progress_bar = Arc::new(if progress && !infinite {
         Some(create_progress_bar(
-            total_size as u64,
+            size.to_bytes() as u64,
             size.is_binary_unit(),
         ))
     } else {
         None
     });
 
-    let chars_len = chars.len();
-    let chars = Arc::new(chars);
+    if !bom.is_empty() {
+        write(output_at(pipeline, &positional, 0, bom.len()), bom.clone())?;
+
+        if let Some(progress_bar) = progress_bar.as_ref() {
+            progress_bar.inc(bom.len() as u64);
+        }
+    }
+
+    let content = Arc::new(content);
     let buf_size = Arc::new(buf_size);
 
     let mut handles = vec![];
+    let mut offset = bom.len();
 
     for chunk_size in chunks {
         if chunk_size == 0 && !infinite {
             continue;
         }
 
-        let writer = Arc::clone(&writer);
+        let mut output = output_at(pipeline, &positional, offset, chunk_size);
+        offset += chunk_size;
+
         let progress_bar = Arc::clone(&progress_bar);
+        let limiter = limiter.clone();
+        let deadline = deadline.clone();
 
-        let chars = Arc::clone(&chars);
-        let byte_count = chunk_size * SIMUL_BYTES;
-        let buf_size = buf_size.unwrap_or(byte_count);
+        let content = Arc::clone(&content);
+        let buf_size = buf_size
+            .unwrap_or((BYTES_PER_WRITE / min_byte_size).max(1) * min_byte_size)
+            .min(max_buf_size);
 
         let handle = thread::spawn(move || {
             let mut rng = rand::thread_rng();
+
             if infinite {
-                let buf_size = SIMUL_BYTES * 128;
+                // Each buffer only ever holds whole characters
+                let buf_size = 1024.min(max_buf_size) / min_byte_size * min_byte_size;
                 let mut buffer = Vec::with_capacity(buf_size);
 
                 loop {
-                    generate_random_ascii_8(
-                        buf_size / SIMUL_BYTES,
-                        &mut rng,
-                        chars_len,
-                        &mut buffer,
-                        &chars,
-                        &progress_bar,
-                    );
-
-                    if write_from_buffer(&writer, &mut buffer).is_err() {
+                    if deadline.as_ref().is_some_and(|d| d.has_passed()) {
                         break;
                     }
+
+                    content.generate(buf_size, &mut rng, &mut buffer, &progress_bar);
+
+                    let produced = buffer.len();
+
+                    match write_from_buffer(&mut output, &mut buffer, &limiter).map_err(Error::from)
+                    {
+                        // The reader stopped reading, which ends the stream
+                        Err(e) if e.is_broken_pipe() => break,
+                        result => result?,
+                    }
+
+                    if let Some(deadline) = &deadline {
+                        deadline.add(produced);
+                    }
                 }
 
-                return;
+                return Ok(());
             }
 
             let mut buffer = Vec::with_capacity(buf_size);
-            let rounds = byte_count / buf_size;
-            let remainder = byte_count % buf_size;
+            let rounds = chunk_size / buf_size;
+            let remainder = chunk_size % buf_size;
+            let mut written = 0;
 
             for _ in 0..rounds {
-                generate_random_ascii_8(
-                    buf_size / SIMUL_BYTES,
-                    &mut rng,
-                    chars_len,
-                    &mut buffer,
-                    &chars,
-                    &progress_bar,
-                );
-
-                write_from_buffer(&writer, &mut buffer).expect("Failed to write to buffer");
-            }
+                content.generate(buf_size, &mut rng, &mut buffer, &progress_bar);
 
-            if remainder < SIMUL_BYTES {
-                return;
+                written += buffer.len();
+                write_from_buffer(&mut output, &mut buffer, &limiter)?;
             }
 
-            let remainder_simul = remainder / SIMUL_BYTES * SIMUL_BYTES;
+            if remainder > 0 {
+                content.generate(remainder, &mut rng, &mut buffer, &progress_bar);
 
-            generate_random_ascii_8(
-                remainder_simul / SIMUL_BYTES,
-                &mut rng,
-                chars_len,
-                &mut buffer,
-                &chars,
-                &progress_bar,
-            );
+                written += buffer.len();
+                write_from_buffer(&mut output, &mut buffer, &limiter)?;
+            }
 
-            generate_random_ascii(
-                remainder - remainder_simul,
-                rng,
-                chars_len,
-                &mut buffer,
-                chars,
-                &progress_bar,
-            );
+            // A short part would leave a gap in a positional file
+            if written != chunk_size {
+                return Err(Error::input(format!(
+                    "Only {written} of {chunk_size} bytes could be generated with the charset"
+                )));
+            }
 
-            write_from_buffer(&writer, &mut buffer).expect("Failed to write to buffer");
+            Ok(())
         });
 
         handles.push(handle);
     }
 
-    for handle in handles {
-        handle.join().expect("Thread panicked");
-    }
+    join(handles)?;
 
-    if infinite {
-        return;
+    if let Some(deadline) = deadline {
+        deadline.report("bytes");
     }
 
-    let leftover_bytes = remaining_bytes % SIMUL_BYTES;
-
-    if leftover_bytes > 0 {
-        let rng = rand::thread_rng();
-        let mut buffer = Vec::with_capacity(leftover_bytes);
-
-        generate_random_ascii(
-            leftover_bytes,
-            rng,
-            chars_len,
-            &mut buffer,
-            chars,
-            &progress_bar,
-        );
-
-        write_from_buffer(&writer, &mut buffer).expect("Failed to write to buffer");
-    }
-}
-
-fn generate_unicode<T: Write + Send + 'static>(
-    size: Option<ByteSize>,
-    encoding: UnicodeEncoding,
-    charset: Option<String>,
-    exclude: Option<String>,
-    threads: Option<NonZeroUsize>,
-    buf_size: Option<ByteSize>,
-    progress: bool,
-    writer: Arc<Mutex<T>>,
-    daemon: bool,
-) {
-    if daemon {
-        panic!("--daemon option is not supported for unicode generation yet");
-    }
-
-    let min_byte_size = match encoding {
-        UnicodeEncoding::Utf8 => 1,
-        UnicodeEncoding::Utf16 => 2,
-        UnicodeEncoding::Utf32 => 4,
-    };
-
-    let size = size.unwrap_or(ByteSize {
-        value: min_byte_size,
-        unit: ByteUnit::B,
-    });
-
-    let num_threads = threads.map(|t| t.get()).unwrap_or_else(num_cpus::get);
-    let buf_size = buf_size.map(|b| b.to_bytes() / num_threads);
-
-    if let Some(ref buf_size) = buf_size {
-        if buf_size < &min_byte_size {
-            panic!(
-                "Buffer size after being divided by the number of threads ({num_threads}) must be {min_byte_size} or greater and divisible by {min_byte_size}"
-            );
+    if let Some(malformed) = &content.malformed {
+        if let Some(progress_bar) = progress_bar.as_ref() {
+            progress_bar.finish();
         }
-    }
-
-    let buf_size = buf_size.map(|b| b / min_byte_size * min_byte_size);
-
-    let total_size = size.to_bytes();
 
-    if total_size < min_byte_size {
-        panic!(
-            "Size too small for encoding.\nMinimum size for {encoding} encoding is {min_byte_size} bytes"
-        );
+        eprintln!("{}", malformed.summary());
     }
 
-    if total_size % min_byte_size != 0 {
-        panic!(
-            "Size must be divisible by {min_byte_size} for {encoding} encoding"
-        );
-    }
+    Ok(())
+}
 
-    let unicode_chars: Vec<char> = (0..=0x10FFFF).filter_map(std::char::from_u32).collect();
+/// The ascii, bytes or unicode generator of a command, set up once to fill any number
+/// of files one after the other on the calling thread. The tree command fills its files with it.
+pub struct FileContent {
+    content: Content,
+    deduplicator: Option<Deduplicator>,
+    limiter: Option<Arc<RateLimiter>>,
+    /// How many bytes to generate per write, a multiple of `unit`.
+    buf_size: usize,
+    unit: usize,
+}
 
-    let mut chars = charset
-        .unwrap_or_else(|| unicode_chars.iter().collect())
-        .chars()
-        .collect::<Vec<char>>();
+enum Content {
+    Ascii(AsciiContent),
+    Bytes(BytesContent),
+    Unicode(UnicodeContent),
+}
 
-    if let Some(e) = exclude {
-        chars.retain(|c| !e.chars().collect::<Vec<char>>().contains(c));
-    }
+impl FileContent {
+    pub fn new(command: Command) -> Result<Self> {
+        let (content, dedupe, bandwidth, buf_size, unit) = match command {
+            Command::Ascii {
+                size: None,
+                duration: None,
+                threads: None,
+                progress: false,
+                charset,
+                printable_only,
+                exclude,
+                exclude_codes,
+                compressibility,
+                dedupe_ratio,
+                dedupe_block_size,
+                dedupe_distance,
+                bandwidth,
+                buf_size,
+            } => {
+                let dedupe = dedupe_ratio
+                    .map(|ratio| Dedupe::new(ratio, dedupe_block_size, dedupe_distance))
+                    .transpose()?;
+
+                let content = AsciiContent::new(
+                    charset,
+                    printable_only,
+                    exclude,
+                    exclude_codes,
+                    compressibility,
+                )?;
+
+                let unit = ascii_unit(dedupe)?;
+                (Content::Ascii(content), dedupe, bandwidth, buf_size, unit)
+            }
+            Command::Bytes {
+                size: None,
+                duration: None,
+                threads: None,
+                progress: false,
+                compressibility,
+                dedupe_ratio,
+                dedupe_block_size,
+                dedupe_distance,
+                bandwidth,
+                buf_size,
+            } => {
+                let dedupe = dedupe_ratio
+                    .map(|ratio| Dedupe::new(ratio, dedupe_block_size, dedupe_distance))
+                    .transpose()?;
+
+                let content = BytesContent::new(compressibility);
+                let unit = dedupe.map_or(1, |d| d.block_size);
+                (Content::Bytes(content), dedupe, bandwidth, buf_size, unit)
+            }
+            Command::Unicode {
+                size: None,
+                duration: None,
+                threads: None,
+                progress: false,
+                encoding,
+                charset,
+                exclude,
+                script,
+                block,
+                category,
+                exclude_category,
+                assigned_only,
+                endian,
+                bom,
+                malformed,
+                bandwidth,
+                buf_size,
+            } => {
+                let content = UnicodeContent::new(
+                    encoding,
+                    charset,
+                    exclude,
+                    script,
+                    block,
+                    category,
+                    exclude_category,
+                    assigned_only,
+                    endian,
+                    bom,
+                    malformed,
+                )?;
+
+                let unit = content.min_byte_size();
+                (Content::Unicode(content), None, bandwidth, buf_size, unit)
+            }
+            Command::Ascii { .. } | Command::Bytes { .. } | Command::Unicode { .. } => {
+                return Err(Error::input(
+                    "The size, duration, threads and progress of each file are up to the tree command",
+                ));
+            }
+            _ => {
+                return Err(Error::input(
+                    "Files can only be filled by ascii, bytes or unicode",
+                ))
+            }
+        };
 
-    if chars.is_empty() {
-        panic!("Charset cannot be empty");
-    }
+        let limiter = bandwidth.map(|b| Arc::new(RateLimiter::new(b.bytes_per_second)));
 
-    let base_size = total_size / (num_threads * min_byte_size);
-    let remainder = (total_size / min_byte_size) % num_threads;
+        // Keep the writes small enough for the rate limit to even them out
+        let max_buf_size = limiter
+            .as_ref()
+            .map_or(usize::MAX, |l| l.chunk_size(1, unit));
 
-    let mut chunks = vec![base_size * min_byte_size; num_threads];
+        let buf_size = buf_size.map_or(BYTES_PER_WRITE, |b| b.to_bytes());
+        let buf_size = ((buf_size / unit).max(1) * unit).min(max_buf_size);
 
-    for chunk in chunks.iter_mut().take(remainder) {
-        *chunk += min_byte_size;
+        Ok(FileContent {
+            content,
+            deduplicator: dedupe.map(Deduplicator::new),
+            limiter,
+            buf_size,
+            unit,
+        })
     }
 
-    let progress_bar = Arc::new(if progress {
-        Some(create_progress_bar(
-            total_size as u64,
-            size.is_binary_unit(),
-        ))
-    } else {
-        None
-    });
-
-    let chars_len = chars.len();
-    let chars = Arc::new(chars);
-    let encoding = Arc::new(encoding);
-    let buf_size = Arc::new(buf_size);
-
-    let mut handles = vec![];
-
-    for chunk_size in chunks {
-        if chunk_size == 0 {
-            continue;
-        }
+    /// A deduplicator for each thread which writes files, all with the same history.
+    pub fn deduplicator(&self) -> Option<Deduplicator> {
+        self.deduplicator.as_ref().map(Deduplicator::share)
+    }
 
-        let writer = Arc::clone(&writer);
-        let progress_bar = Arc::clone(&progress_bar);
+    /// Writes `size` bytes to the writer.
+    /// Unicode files hold whole characters, so their size is rounded down to those.
+    pub fn write(
+        &self,
+        size: usize,
+        writer: &mut impl Write,
+        deduplicator: &mut Option<Deduplicator>,
+        rng: &mut ThreadRng,
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        let no_progress = Arc::new(None);
+
+        let mut remaining = match &self.content {
+            Content::Unicode(unicode) if size < unicode.bom.len() => 0,
+            Content::Unicode(unicode) => {
+                writer.write_all(&unicode.bom)?;
+                (size - unicode.bom.len()) / self.unit * self.unit
+            }
+            _ => size,
+        };
 
-        let chars = Arc::clone(&chars);
-        let encoding = Arc::clone(&encoding);
-        let buf_size = buf_size.unwrap_or(chunk_size);
+        while remaining > 0 {
+            let bytes = remaining.min(self.buf_size);
+            buffer.clear();
 
-        let handle = thread::spawn(move || {
-            let mut rng = rand::thread_rng();
-            let mut buffer = Vec::with_capacity(buf_size);
-            let rounds = chunk_size / buf_size;
-            let remainder = chunk_size % buf_size;
-            let mut current_bytes = 0;
+            let generated = match &self.content {
+                Content::Ascii(ascii) => {
+                    ascii.generate(bytes, rng, buffer, &no_progress);
+                    bytes
+                }
+                Content::Bytes(content) => {
+                    content.generate(bytes, rng, buffer, &no_progress);
+                    bytes
+                }
+                Content::Unicode(unicode) => unicode.generate(bytes, rng, buffer, &no_progress),
+            };
 
-            for _ in 0..rounds {
-                generate_random_unicode(
-                    &encoding,
-                    &mut current_bytes,
-                    buf_size,
-                    &mut rng,
-                    chars_len,
-                    &chars,
-                    &mut buffer,
-                    &progress_bar,
-                );
-
-                write_from_buffer(&writer, &mut buffer).expect("Failed to write to buffer");
-                current_bytes = 0;
+            if generated != bytes {
+                return Err(Error::input(format!(
+                    "Only {generated} of {bytes} bytes could be generated with the charset"
+                )));
             }
 
-            if remainder < min_byte_size {
-                return;
-            }
+            // A partial block at the end of a file is left as it is
+            deduplicate(deduplicator, buffer);
 
-            generate_random_unicode(
-                &encoding,
-                &mut current_bytes,
-                remainder,
-                &mut rng,
-                chars_len,
-                &chars,
-                &mut buffer,
-                &progress_bar,
-            );
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire(buffer.len());
+            }
 
-            write_from_buffer(&writer, &mut buffer).expect("Failed to write to buffer");
-        });
+            writer.write_all(buffer)?;
+            remaining -= bytes;
+        }
 
-        handles.push(handle);
+        Ok(())
     }
 
-    for handle in handles {
-        handle.join().expect("Thread panicked");
+    /// Reports what was injected with --malformed.
+    pub fn finish(&self) {
+        if let Content::Unicode(UnicodeContent {
+            malformed: Some(malformed),
+            ..
+        }) = &self.content
+        {
+            eprintln!("{}", malformed.summary());
+        }
     }
 }
 
+/// Appends `amount` values to the buffer, each on its own line.
 #[inline(always)]
-fn generate_random_value<T, F: FnMut() -> T>(
+fn generate_random_value<T: Format, F: FnMut() -> Result<T>>(
     amount: usize,
     gen_func: &mut F,
-    buffer: &mut Vec<T>,
+    buffer: &mut Vec<u8>,
     progress_bar: &Arc<Option<ProgressBar>>,
-) {
+) -> Result<()> {
     for _ in 0..amount {
-        let value = gen_func();
-        buffer.push(value);
+        gen_func()?.format(buffer);
+        buffer.push(b'\n');
+    }
 
-        if let Some(progress_bar) = progress_bar.as_ref().clone() {
-            progress_bar.inc(1);
-        }
+    if let Some(progress_bar) = progress_bar.as_ref() {
+        progress_bar.inc(amount as u64);
     }
+
+    Ok(())
 }
 
 #[inline(always)]
@@ -988,10 +1729,10 @@ fn generate_random_ascii_8(
 #[inline(always)]
 fn generate_random_ascii(
     bytes: usize,
-    mut rng: rand::prelude::ThreadRng,
+    rng: &mut rand::prelude::ThreadRng,
     chars_len: usize,
     buffer: &mut Vec<u8>,
-    chars: Arc<Vec<char>>,
+    chars: &Arc<Vec<char>>,
     progress_bar: &Arc<Option<ProgressBar>>,
 ) {
     for _ in 0..bytes {
@@ -999,112 +1740,220 @@ fn generate_random_ascii(
         buffer.push(chars[num] as u8);
 
         if let Some(progress_bar) = progress_bar.as_ref().clone() {
-            progress_bar.inc(SIMUL_BYTES as u64);
+            progress_bar.inc(1);
         }
     }
 }
 
 #[inline(always)]
-fn write<T: Write>(writer: &Arc<Mutex<T>>, content: &[u8]) {
-    let mut writer = writer.lock().expect("Failed to lock writer");
-    writer
-        .write_all(content)
-        .expect("Failed to write to buffer");
+fn generate_random_bytes(
+    bytes: usize,
+    rng: &mut rand::prelude::ThreadRng,
+    buffer: &mut Vec<u8>,
+    progress_bar: &Arc<Option<ProgressBar>>,
+) {
+    let start = buffer.len();
+    buffer.resize(start + bytes, 0);
+    rng.fill(&mut buffer[start..]);
+
+    if let Some(progress_bar) = progress_bar.as_ref() {
+        progress_bar.inc(bytes as u64);
+    }
 }
 
 #[inline(always)]
-fn writeln<T: Write, U: ToString>(writer: &Arc<Mutex<T>>, buffer: &mut Vec<U>) -> Result<()> {
-    let mut writer = writer.lock().expect("Failed to lock writer");
-
-    writer.write_all(
-        buffer
-            .iter()
-            .map(|i| i.to_string())
-            .collect::<Vec<_>>()
-            .join("\n")
-            .as_bytes(),
-    )?;
+fn deduplicate(deduplicator: &mut Option<Deduplicator>, buffer: &mut [u8]) {
+    if let Some(deduplicator) = deduplicator {
+        deduplicator.apply(buffer);
+    }
+}
 
-    writer.write_all(b"\n")?;
+/// The size of the segments --compressibility splits the output into.
+/// Each segment has the same ratio of random to repeated bytes,
+/// so any part of the output compresses about as well as the whole.
+const COMPRESSIBLE_SEGMENT_SIZE: usize = 64 * 1024;
 
-    buffer.clear();
-    Ok(())
+/// Overwrites the end of every segment of the buffer with `fill`
+/// so that only the `random` fraction at the start of each segment stays random.
+#[inline(always)]
+fn make_compressible(buffer: &mut [u8], random: Option<f64>, fill: u8) {
+    let Some(random) = random else {
+        return;
+    };
+
+    for segment in buffer.chunks_mut(COMPRESSIBLE_SEGMENT_SIZE) {
+        let keep = (segment.len() as f64 * random).round() as usize;
+        segment[keep..].fill(fill);
+    }
+}
+
+/// Writes `len` bytes at `offset` of a positional file, or to the pipeline.
+fn output_at(
+    pipeline: &Pipeline,
+    positional: &Option<Arc<PositionalFile>>,
+    offset: usize,
+    len: usize,
+) -> Output {
+    match positional {
+        Some(file) => Output::Region(file.at(offset, len)),
+        None => pipeline.output(),
+    }
 }
 
 #[inline(always)]
-fn write_from_buffer<T: Write>(writer: &Arc<Mutex<T>>, buffer: &mut Vec<u8>) -> Result<()> {
-    let mut writer = writer.lock().expect("Failed to lock writer");
-    writer.write_all(buffer)?;
-    buffer.clear();
-    Ok(())
+fn write(mut output: Output, mut content: Vec<u8>) -> io::Result<()> {
+    output.send(&mut content)
+}
+
+/// Waits for every generator thread and returns the first error.
+fn join(handles: Vec<JoinHandle<Result<()>>>) -> Result<()> {
+    let mut result = Ok(());
+
+    for handle in handles {
+        let joined = handle.join().expect("Thread panicked");
+        result = result.and(joined);
+    }
+
+    result
+}
+
+/// Writes a buffer of `values` lines.
+#[inline(always)]
+fn writeln(
+    output: &mut Output,
+    buffer: &mut Vec<u8>,
+    values: usize,
+    limiter: &Option<Arc<RateLimiter>>,
+) -> io::Result<()> {
+    if let Some(limiter) = limiter {
+        limiter.acquire(values);
+    }
+
+    output.send(buffer)
+}
+
+#[inline(always)]
+fn write_from_buffer(
+    output: &mut Output,
+    buffer: &mut Vec<u8>,
+    limiter: &Option<Arc<RateLimiter>>,
+) -> io::Result<()> {
+    if let Some(limiter) = limiter {
+        limiter.acquire(buffer.len());
+    }
+
+    output.send(buffer)
 }
 
 #[inline(always)]
 fn generate_random_unicode(
     encoding: &Arc<UnicodeEncoding>,
+    endian: Endianness,
     current_bytes: &mut usize,
     chunk_size: usize,
     rng: &mut rand::prelude::ThreadRng,
-    chars_len: usize,
+    lengths: &EncodedLengths,
     chars: &Arc<Vec<char>>,
     buffer: &mut Vec<u8>,
+    malformed: &Option<Arc<Malformed>>,
     progress_bar: &Arc<Option<ProgressBar>>,
 ) {
     match **encoding {
         UnicodeEncoding::Utf8 => {
             while *current_bytes < chunk_size {
-                let char_index = rng.gen_range(0..chars_len);
-                let ch = chars[char_index];
-
-                let len = ch.len_utf8();
+                let remaining = chunk_size - *current_bytes;
 
-                if *current_bytes + len <= chunk_size {
-                    let mut buf = [0; 4];
-                    let bytes = ch.encode_utf8(&mut buf).as_bytes();
-                    buffer.extend_from_slice(bytes);
+                if let Some(len) = inject_malformed(malformed, lengths, remaining, rng, buffer) {
                     *current_bytes += len;
 
                     if let Some(progress_bar) = progress_bar.as_ref().clone() {
                         progress_bar.inc(len as u64);
                     }
+
+                    continue;
+                }
+
+                // Doesn't happen for chunks which are a multiple of the shortest character
+                let Some(char_index) = lengths.pick(remaining, rng) else {
+                    break;
+                };
+
+                let ch = chars[char_index];
+
+                let len = ch.len_utf8();
+
+                let mut buf = [0; 4];
+                let bytes = ch.encode_utf8(&mut buf).as_bytes();
+                buffer.extend_from_slice(bytes);
+                *current_bytes += len;
+
+                if let Some(progress_bar) = progress_bar.as_ref().clone() {
+                    progress_bar.inc(len as u64);
                 }
             }
         }
         UnicodeEncoding::Utf16 => {
             while *current_bytes < chunk_size {
-                let char_index = rng.gen_range(0..chars_len);
+                let remaining = chunk_size - *current_bytes;
+
+                if let Some(len) = inject_malformed(malformed, lengths, remaining, rng, buffer) {
+                    *current_bytes += len;
+
+                    if let Some(progress_bar) = progress_bar.as_ref().clone() {
+                        progress_bar.inc(len as u64);
+                    }
+
+                    continue;
+                }
+
+                // Doesn't happen for chunks which are a multiple of the shortest character
+                let Some(char_index) = lengths.pick(remaining, rng) else {
+                    break;
+                };
+
                 let ch = chars[char_index];
 
-                // Each UTF-16 unit is 2 bytes
                 let len = ch.len_utf16() * 2;
 
-                if *current_bytes + len <= chunk_size {
-                    let mut buf = [0; 2];
-                    let bytes = ch.encode_utf16(&mut buf);
+                let mut buf = [0; 2];
 
-                    buffer.extend_from_slice(
-                        &bytes
-                            .iter()
-                            .flat_map(|b| b.to_le_bytes())
-                            .collect::<Vec<_>>(),
-                    );
+                for unit in ch.encode_utf16(&mut buf) {
+                    buffer.extend_from_slice(&match endian {
+                        Endianness::Little => unit.to_le_bytes(),
+                        Endianness::Big => unit.to_be_bytes(),
+                    });
+                }
 
-                    *current_bytes += len;
+                *current_bytes += len;
 
-                    if let Some(progress_bar) = progress_bar.as_ref().clone() {
-                        progress_bar.inc(len as u64);
-                    }
+                if let Some(progress_bar) = progress_bar.as_ref().clone() {
+                    progress_bar.inc(len as u64);
                 }
             }
         }
         UnicodeEncoding::Utf32 => {
             while *current_bytes < chunk_size {
-                let char_index = rng.gen_range(0..chars_len);
+                let remaining = chunk_size - *current_bytes;
+
+                if let Some(len) = inject_malformed(malformed, lengths, remaining, rng, buffer) {
+                    *current_bytes += len;
+
+                    if let Some(progress_bar) = progress_bar.as_ref().clone() {
+                        progress_bar.inc(len as u64);
+                    }
+
+                    continue;
+                }
+
+                let char_index = rng.gen_range(0..chars.len());
                 let ch = chars[char_index];
 
                 const LEN: usize = 4;
 
-                buffer.extend_from_slice(&(ch as u32).to_le_bytes());
+                buffer.extend_from_slice(&match endian {
+                    Endianness::Little => (ch as u32).to_le_bytes(),
+                    Endianness::Big => (ch as u32).to_be_bytes(),
+                });
                 *current_bytes += LEN;
 
                 if let Some(progress_bar) = progress_bar.as_ref().clone() {
@@ -1116,10 +1965,115 @@ fn generate_random_unicode(
 }
 
 #[inline(always)]
-fn gen_str(length: Option<usize>) -> String {
-    match length {
-        Some(l) => generate(l, charsets::ALPHA_LOWER),
-        None => generate_rng(5..15, charsets::ALPHA_LOWER),
+fn inject_malformed(
+    malformed: &Option<Arc<Malformed>>,
+    lengths: &EncodedLengths,
+    remaining: usize,
+    rng: &mut rand::prelude::ThreadRng,
+    buffer: &mut Vec<u8>,
+) -> Option<usize> {
+    let malformed = malformed.as_ref()?;
+
+    match malformed.inject(|len| lengths.fits(len, remaining), rng, buffer) {
+        0 => None,
+        len => Some(len),
+    }
+}
+
+/// The characters of a sorted charset grouped by their encoded length, to only pick
+/// characters which leave a remainder that can still be filled exactly.
+/// E.g. with only 2 and 3 byte characters, 4 bytes can't start with a 3 byte character.
+#[derive(Debug)]
+struct EncodedLengths {
+    /// Where the characters of each length from 1 to 4 bytes start, followed by the end.
+    starts: [usize; 5],
+    /// Whether each small number of bytes can be filled exactly.
+    fillable: [bool; 16],
+    /// Larger numbers of bytes can be filled exactly when they are a multiple of this.
+    gcd: usize,
+}
+
+impl EncodedLengths {
+    /// `encoded_len` has to grow with the code point, which holds for every encoding.
+    fn new(chars: &[char], encoded_len: impl Fn(char) -> usize) -> Self {
+        let mut starts = [chars.len(); 5];
+
+        for (len, start) in starts.iter_mut().enumerate().take(4) {
+            *start = chars.partition_point(|c| encoded_len(*c) <= len);
+        }
+
+        let mut lengths = EncodedLengths {
+            starts,
+            fillable: [false; 16],
+            gcd: 0,
+        };
+
+        let lens: Vec<usize> = lengths.lens().collect();
+        lengths.fillable[0] = true;
+
+        for bytes in 1..16 {
+            lengths.fillable[bytes] = lens
+                .iter()
+                .any(|len| *len <= bytes && lengths.fillable[bytes - len]);
+        }
+
+        lengths.gcd = lens.into_iter().fold(0, gcd);
+        lengths
+    }
+
+    /// The encoded lengths which some characters have.
+    fn lens(&self) -> impl Iterator<Item = usize> + '_ {
+        (1..=4).filter(|len| self.starts[len - 1] < self.starts[*len])
+    }
+
+    /// The length of the shortest character.
+    fn min(&self) -> usize {
+        self.lens().next().expect("Charsets aren't empty")
+    }
+
+    fn fillable(&self, bytes: usize) -> bool {
+        match self.fillable.get(bytes) {
+            Some(fillable) => *fillable,
+            // Every multiple of the gcd from 16 bytes on is a sum of lengths up to 4
+            None => bytes.is_multiple_of(self.gcd),
+        }
+    }
+
+    /// Whether writing `len` of the `remaining` bytes leaves a remainder which can be filled.
+    fn fits(&self, len: usize, remaining: usize) -> bool {
+        len <= remaining && self.fillable(remaining - len)
+    }
+
+    /// Picks the index of a character which fits in the `remaining` bytes.
+    fn pick(&self, remaining: usize, rng: &mut rand::prelude::ThreadRng) -> Option<usize> {
+        let total = self.starts[4];
+
+        if remaining >= 20 && remaining.is_multiple_of(self.gcd) {
+            return Some(rng.gen_range(0..total));
+        }
+
+        let count = |len: usize| self.starts[len] - self.starts[len - 1];
+        let fitting = || self.lens().filter(|len| self.fits(*len, remaining));
+
+        let mut n = rng.gen_range(0..fitting().map(count).sum::<usize>().max(1));
+
+        for len in fitting() {
+            if n < count(len) {
+                return Some(self.starts[len - 1] + n);
+            }
+
+            n -= count(len);
+        }
+
+        None
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
 }
 
@@ -1147,7 +2101,7 @@ fn create_progress_bar(total_size: u64, is_binary_bytes: bool) -> ProgressBar {
     progress_bar
 }
 
-fn create_progress_bar_amount(total_size: u64) -> ProgressBar {
+pub fn create_progress_bar_amount(total_size: u64) -> ProgressBar {
     let progress_bar = ProgressBar::new(total_size);
 
     let style = ProgressStyle::default_bar()
@@ -1162,4 +2116,137 @@ fn create_progress_bar_amount(total_size: u64) -> ProgressBar {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::{io::Write, sync::Arc};
+
+    use flate2::{write::GzEncoder, Compression as GzCompression};
+
+    use super::{
+        generate_random_unicode, make_compressible, BytesContent, EncodedLengths,
+        COMPRESSIBLE_SEGMENT_SIZE,
+    };
+    use crate::{
+        args::{Endianness, Ratio, UnicodeEncoding},
+        charset::Charset,
+    };
+
+    fn chars(charset: &str) -> Vec<char> {
+        charset.parse::<Charset>().unwrap().into_chars()
+    }
+
+    fn utf8_lengths(charset: &str) -> (Vec<char>, EncodedLengths) {
+        let mut chars = chars(charset);
+        chars.sort_unstable();
+        let lengths = EncodedLengths::new(&chars, char::len_utf8);
+        (chars, lengths)
+    }
+
+    #[test]
+    fn encoded_lengths_fillable() {
+        let (_, lengths) = utf8_lengths("ö+一");
+        assert_eq!(lengths.min(), 2);
+        assert!(!lengths.fillable(1));
+        assert!(lengths.fillable(5));
+        assert!(lengths.fillable(1001));
+
+        let (_, lengths) = utf8_lengths("一-龥");
+        assert_eq!(lengths.min(), 3);
+        assert!(!lengths.fillable(10));
+        assert!(lengths.fillable(9));
+        assert!(!lengths.fillable(1000));
+    }
+
+    #[test]
+    fn encoded_lengths_pick_leaves_fillable_remainder() {
+        let (chars, lengths) = utf8_lengths("ö+一");
+        let mut rng = rand::thread_rng();
+
+        // 3 bytes would leave 1 byte, which no character fills
+        for _ in 0..100 {
+            let i = lengths.pick(4, &mut rng).unwrap();
+            assert_eq!(chars[i], 'ö');
+        }
+
+        assert_eq!(lengths.pick(1, &mut rng), None);
+    }
+
+    #[test]
+    fn unicode_fills_chunks_exactly() {
+        for (charset, size) in [("ö+一", 10), ("一-龥", 9), ("a+𐀀", 7), ("ö+一-龥+𐀀", 41)]
+        {
+            let (chars, lengths) = utf8_lengths(charset);
+            let mut buffer = vec![];
+
+            generate_random_unicode(
+                &Arc::new(UnicodeEncoding::Utf8),
+                Endianness::Little,
+                &mut 0,
+                size,
+                &mut rand::thread_rng(),
+                &lengths,
+                &Arc::new(chars),
+                &mut buffer,
+                &None,
+                &Arc::new(None),
+            );
+
+            assert_eq!(buffer.len(), size, "{charset}");
+            assert!(std::str::from_utf8(&buffer).is_ok());
+        }
+    }
+
+    #[test]
+    fn compressible_segments() {
+        const SEGMENT: usize = COMPRESSIBLE_SEGMENT_SIZE;
+
+        let mut buffer = vec![1; 2 * SEGMENT + 100];
+        make_compressible(&mut buffer, None, 0);
+        assert!(buffer.iter().all(|b| *b == 1));
+
+        // The start of every segment stays, including the partial one at the end
+        make_compressible(&mut buffer, Some(0.25), 0);
+
+        for (i, segment) in buffer.chunks(SEGMENT).enumerate() {
+            let kept = segment.iter().take_while(|b| **b == 1).count();
+            let expected = if i < 2 { SEGMENT / 4 } else { 25 };
+
+            assert_eq!(kept, expected);
+            assert!(segment[kept..].iter().all(|b| *b == 0));
+        }
+
+        let mut buffer = vec![1; SEGMENT];
+        make_compressible(&mut buffer, Some(1.0), 0);
+        assert!(buffer.iter().all(|b| *b == 1));
+
+        make_compressible(&mut buffer, Some(0.0), b'x');
+        assert!(buffer.iter().all(|b| *b == b'x'));
+    }
+
+    #[test]
+    fn compressibility_of_bytes() {
+        for compressibility in ["0", "50%", "0.9"] {
+            let ratio = compressibility.parse::<Ratio>().unwrap();
+            let content = BytesContent::new(Some(ratio));
+
+            let mut buffer = vec![];
+            content.generate(
+                1 << 20,
+                &mut rand::thread_rng(),
+                &mut buffer,
+                &Arc::new(None),
+            );
+            assert_eq!(buffer.len(), 1 << 20);
+
+            let mut encoder = GzEncoder::new(vec![], GzCompression::default());
+            encoder.write_all(&buffer).unwrap();
+            let compressed = encoder.finish().unwrap().len() as f64 / buffer.len() as f64;
+
+            // Random bytes don't compress and the rest nearly disappears
+            let expected = 1.0 - ratio.value;
+            assert!(
+                (compressed - expected).abs() < 0.03,
+                "{compressibility}: {compressed}"
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A token bucket shared between the worker threads.
+/// A token is either a value or a byte depending on the generator.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64) -> Self {
+        // Allow bursts of a tenth of a second worth of tokens
+        // so that the output stays steady instead of coming in large batches.
+        let burst = (per_second / 10.0).max(1.0);
+
+        RateLimiter {
+            per_second,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// The number of tokens each of `num_threads` threads should
+    /// take at a time, rounded down to a multiple of `granularity`.
+    pub fn chunk_size(&self, num_threads: usize, granularity: usize) -> usize {
+        let chunk = (self.burst as usize / num_threads).max(1);
+        (chunk / granularity * granularity).max(granularity)
+    }
+
+    /// Takes `tokens` from the bucket, blocking until they have been refilled.
+    /// Requests larger than the bucket are allowed and put it into debt,
+    /// which makes every following request wait until it is paid off.
+    pub fn acquire(&self, tokens: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().expect("Failed to lock rate limiter");
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+
+            bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+            bucket.refilled_at = now;
+            bucket.tokens -= tokens as f64;
+
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+
+            Duration::from_secs_f64(-bucket.tokens / self.per_second)
+        };
+
+        thread::sleep(wait);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::RateLimiter;
+
+    #[test]
+    fn chunk_sizes() {
+        // A burst of 100 tokens
+        let limiter = RateLimiter::new(1000.0);
+
+        assert_eq!(limiter.chunk_size(1, 1), 100);
+        assert_eq!(limiter.chunk_size(3, 1), 33);
+        assert_eq!(limiter.chunk_size(3, 8), 32);
+
+        // Never less than a whole unit
+        assert_eq!(limiter.chunk_size(1000, 1), 1);
+        assert_eq!(limiter.chunk_size(1, 4096), 4096);
+    }
+
+    #[test]
+    fn bursts_without_waiting() {
+        let limiter = RateLimiter::new(1000.0);
+        let start = Instant::now();
+
+        limiter.acquire(60);
+        limiter.acquire(40);
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn waits_for_debt() {
+        let limiter = RateLimiter::new(1000.0);
+        let start = Instant::now();
+
+        // The burst and then 200 tokens more, which take 200ms to refill
+        limiter.acquire(300);
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(190), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(1000), "{elapsed:?}");
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let limiter = RateLimiter::new(1000.0);
+        let start = Instant::now();
+
+        // 400 tokens, of which the burst is free
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..4 {
+                        limiter.acquire(25);
+                    }
+                });
+            }
+        });
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(250), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(1500), "{elapsed:?}");
+    }
+}
@@ -0,0 +1,310 @@
+use std::str::FromStr;
+
+use rand::{rngs::ThreadRng, Rng};
+use regex_syntax::hir::{self, Hir, HirKind};
+
+use crate::error::Error;
+
+/// The first and last code point of the surrogates, which aren't characters.
+const SURROGATES: (u32, u32) = (0xD800, 0xDFFF);
+
+/// How many strings to try for a pattern with anchors or word boundaries
+/// before giving up on finding one which matches, e.g. for `a^b`.
+const MATCH_ATTEMPTS: usize = 10_000;
+
+/// A regular expression to generate matching strings for, e.g. `[A-Z]{3}-\d{4}`.
+///
+/// Anchors and word boundaries don't generate anything,
+/// so strings are generated until one of them matches the whole pattern.
+/// Classes with printable ASCII characters only generate those,
+/// so `\d` generates 0-9 rather than any Unicode digit, unless `unicode` is set.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    hir: Hir,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(String),
+    Class(Class),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        /// None for *, + and {n,}.
+        max: Option<u32>,
+    },
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+}
+
+/// The characters of a class, to pick from uniformly.
+#[derive(Debug, Clone)]
+struct Class {
+    /// Inclusive ranges of code points.
+    ranges: Vec<(u32, u32)>,
+    /// The number of code points up to the end of each range.
+    ends: Vec<u32>,
+}
+
+/// Generates strings which match a pattern.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    node: Node,
+    max_repeat: u32,
+    /// The whole pattern, to check strings against when it has anchors or word boundaries.
+    check: Option<::regex::Regex>,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hir = regex_syntax::Parser::new()
+            .parse(s)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Pattern {
+            source: s.to_owned(),
+            hir,
+        })
+    }
+}
+
+impl Pattern {
+    /// `max_repeat` is the upper bound of repetitions without one, e.g. `*` and `+`.
+    pub fn generator(&self, max_repeat: u32, unicode: bool) -> Option<Generator> {
+        let node = build(&self.hir, unicode)?;
+
+        let check = if self.hir.properties().look_set().is_empty() {
+            None
+        } else {
+            let regex = ::regex::Regex::new(&format!(r"\A(?:{})\z", self.source))
+                .expect("The pattern was already parsed");
+
+            Some(regex)
+        };
+
+        let generator = Generator {
+            node,
+            max_repeat,
+            check,
+        };
+
+        // Reject patterns which never match up front, rather than for the first string
+        if generator.check.is_some() {
+            generator.generate(&mut rand::thread_rng()).ok()?;
+        }
+
+        Some(generator)
+    }
+}
+
+impl Generator {
+    /// Fails when none of a number of strings matches the anchors and word boundaries,
+    /// since then matches are too rare to generate many of them.
+    pub fn generate(&self, rng: &mut ThreadRng) -> Result<String, Error> {
+        let Some(check) = &self.check else {
+            return Ok(self.generate_unchecked(rng));
+        };
+
+        (0..MATCH_ATTEMPTS)
+            .map(|_| self.generate_unchecked(rng))
+            .find(|string| check.is_match(string))
+            .ok_or_else(|| {
+                Error::input(format!(
+                    "The pattern is too restrictive, none of {MATCH_ATTEMPTS} generated strings matched it"
+                ))
+            })
+    }
+
+    fn generate_unchecked(&self, rng: &mut ThreadRng) -> String {
+        let mut string = String::new();
+        self.push(&self.node, rng, &mut string);
+        string
+    }
+
+    fn push(&self, node: &Node, rng: &mut ThreadRng, string: &mut String) {
+        match node {
+            Node::Literal(literal) => string.push_str(literal),
+            Node::Class(class) => string.push(class.pick(rng)),
+            Node::Repeat { node, min, max } => {
+                let max = max.unwrap_or(self.max_repeat.max(*min));
+
+                for _ in 0..rng.gen_range(*min..=max) {
+                    self.push(node, rng, string);
+                }
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.push(node, rng, string);
+                }
+            }
+            Node::Alternation(nodes) => {
+                let node = &nodes[rng.gen_range(0..nodes.len())];
+                self.push(node, rng, string);
+            }
+        }
+    }
+}
+
+/// The node to generate `hir` with, or None if nothing matches it, e.g. `[^\s\S]`.
+fn build(hir: &Hir, unicode: bool) -> Option<Node> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Some(Node::Concat(vec![])),
+        // The parser only allows patterns which match valid UTF-8
+        HirKind::Literal(hir::Literal(bytes)) => {
+            Some(Node::Literal(String::from_utf8_lossy(bytes).into_owned()))
+        }
+        HirKind::Class(class) => Class::new(class, unicode).map(Node::Class),
+        HirKind::Repetition(repetition) => match build(&repetition.sub, unicode) {
+            Some(node) => Some(Node::Repeat {
+                node: Box::new(node),
+                min: repetition.min,
+                max: repetition.max,
+            }),
+            None if repetition.min == 0 => Some(Node::Concat(vec![])),
+            None => None,
+        },
+        HirKind::Capture(capture) => build(&capture.sub, unicode),
+        HirKind::Concat(hirs) => hirs
+            .iter()
+            .map(|hir| build(hir, unicode))
+            .collect::<Option<_>>()
+            .map(Node::Concat),
+        HirKind::Alternation(hirs) => {
+            let nodes: Vec<Node> = hirs.iter().filter_map(|hir| build(hir, unicode)).collect();
+            (!nodes.is_empty()).then_some(Node::Alternation(nodes))
+        }
+    }
+}
+
+impl Class {
+    fn new(class: &hir::Class, unicode: bool) -> Option<Class> {
+        let ranges: Vec<(u32, u32)> = match class {
+            hir::Class::Unicode(class) => {
+                let mut printable = hir::ClassUnicode::new([hir::ClassUnicodeRange::new(' ', '~')]);
+                printable.intersect(class);
+
+                let class = match printable.ranges() {
+                    [] => class,
+                    _ if unicode => class,
+                    _ => &printable,
+                };
+
+                class
+                    .iter()
+                    .map(|range| (range.start() as u32, range.end() as u32))
+                    .collect()
+            }
+            // Only ASCII when the pattern matches UTF-8
+            hir::Class::Bytes(class) => {
+                let mut printable = hir::ClassBytes::new([hir::ClassBytesRange::new(b' ', b'~')]);
+                printable.intersect(class);
+
+                let class = match printable.ranges() {
+                    [] => class,
+                    _ if unicode => class,
+                    _ => &printable,
+                };
+
+                class
+                    .iter()
+                    .map(|range| (range.start() as u32, range.end() as u32))
+                    .collect()
+            }
+        };
+
+        // A range like \u{D000}-\u{E000} spans the surrogates
+        let ranges: Vec<(u32, u32)> = ranges
+            .into_iter()
+            .flat_map(|(start, end)| {
+                if start < SURROGATES.0 && end > SURROGATES.1 {
+                    vec![(start, SURROGATES.0 - 1), (SURROGATES.1 + 1, end)]
+                } else {
+                    vec![(start, end)]
+                }
+            })
+            .collect();
+
+        if ranges.is_empty() {
+            return None;
+        }
+
+        let ends = ranges
+            .iter()
+            .scan(0, |total, (start, end)| {
+                *total += end - start + 1;
+                Some(*total)
+            })
+            .collect();
+
+        Some(Class { ranges, ends })
+    }
+
+    fn pick(&self, rng: &mut ThreadRng) -> char {
+        let total = *self.ends.last().expect("Classes aren't empty");
+        let n = rng.gen_range(0..total);
+        let i = self.ends.partition_point(|end| *end <= n);
+        let before = if i == 0 { 0 } else { self.ends[i - 1] };
+
+        char::from_u32(self.ranges[i].0 + n - before).expect("Surrogates are left out")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+
+    fn regex_samples(pattern: &str) -> Vec<String> {
+        let generator = pattern
+            .parse::<Pattern>()
+            .unwrap()
+            .generator(8, false)
+            .unwrap();
+
+        let mut rng = rand::thread_rng();
+        (0..200)
+            .map(|_| generator.generate(&mut rng).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn regex_generates_matches() {
+        for pattern in [
+            r"[A-Z]{3}-\d{4}",
+            r"(SKU|ART)-[0-9A-F]{8}(-[a-z]{2})?",
+            r"a*b+c?",
+            r"^\w{2,5}$",
+            r"[a-z ]\b[a-z]{2}",
+        ] {
+            let regex = ::regex::Regex::new(&format!(r"\A(?:{pattern})\z")).unwrap();
+
+            for string in regex_samples(pattern) {
+                assert!(
+                    regex.is_match(&string),
+                    "{string:?} doesn't match {pattern}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn regex_printable_ascii_by_default() {
+        assert!(regex_samples(r"\d{4}")
+            .iter()
+            .all(|s| s.chars().all(|c| c.is_ascii_digit())));
+
+        assert!(regex_samples(r"\p{Greek}").iter().all(|s| !s.is_ascii()));
+    }
+
+    #[test]
+    fn regex_rejects_unmatchable_patterns() {
+        for pattern in ["a^b", "x$y", r"[^\s\S]"] {
+            let generator = pattern.parse::<Pattern>().unwrap().generator(8, false);
+            assert!(generator.is_none(), "{pattern}");
+        }
+
+        assert!("(".parse::<Pattern>().is_err());
+    }
+}
@@ -0,0 +1,691 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Result, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::{
+    fs::FileTypeExt,
+    net::{UnixListener, UnixStream},
+};
+
+use clap::{CommandFactory, Parser};
+use serde_json::Value;
+
+use crate::{
+    args::{Command, GenArgs, UnicodeEncoding},
+    compress::{compression, CompressedWriter},
+    error::{self, Error},
+    program,
+    splice::Splice,
+};
+
+/// The maximum length of a single request line.
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// The most threads a single request may generate on.
+const MAX_THREADS: usize = 16;
+
+/// The most values or bytes a single request may ask for,
+/// since every client shares the memory and cores of the server.
+const MAX_TOTAL: usize = 1_000_000_000;
+
+/// The largest buffer a single request may ask for, in values or bytes per thread.
+const MAX_BUF_SIZE: usize = 16_000_000;
+
+/// The longest --duration a single request may ask for, since it holds a worker until then.
+const MAX_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Serve generator output on every given listener until the process is stopped.
+/// Only fails when a listener can't be set up, e.g. because the port is taken.
+pub fn serve(socket: Option<PathBuf>, http: Option<SocketAddr>) -> error::Result<()> {
+    let mut handles = vec![];
+
+    if let Some(socket) = socket {
+        handles.push(thread::spawn(move || {
+            serve_socket(&socket).map_err(|e| Error::destination(socket.display(), e))
+        }));
+    }
+
+    if let Some(addr) = http {
+        handles.push(thread::spawn(move || {
+            serve_http(addr).map_err(|e| Error::destination(addr, e))
+        }));
+    }
+
+    for handle in handles {
+        handle.join().expect("Thread panicked")?;
+    }
+
+    Ok(())
+}
+
+/// Serve generator output over a Unix domain socket.
+/// Each connection sends a single request line and receives the generated output.
+#[cfg(unix)]
+fn serve_socket(path: &Path) -> Result<()> {
+    // A socket left behind by a previous run would make binding fail
+    if path.exists() {
+        if !fs::metadata(path)?.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "File already exists and is not a socket",
+            ));
+        }
+
+        fs::remove_file(path)?;
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = handle_socket_client(stream) {
+                        eprintln!("Failed to serve client: {e}");
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to accept connection: {e}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_socket(_path: &Path) -> Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix domain sockets are not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn handle_socket_client(stream: UnixStream) -> Result<()> {
+    let mut line = String::new();
+
+    BufReader::new(stream.try_clone()?.take(MAX_REQUEST_LEN)).read_line(&mut line)?;
+
+    let args = match parse_request(&line) {
+        Ok(args) => args,
+        Err(e) => {
+            let mut stream = stream;
+            return stream.write_all(e.as_bytes());
+        }
+    };
+
+    let result = match compression(&args) {
+        Some(compression) => {
+            let writer = CompressedWriter::new(stream.try_clone()?, compression);
+            let writer = Arc::new(Mutex::new(writer));
+
+            program::run(args, Arc::clone(&writer), None)
+                .and_then(|()| Ok(writer.lock().expect("Failed to lock writer").finish()?))
+        }
+        None => program::run(args, Arc::new(Mutex::new(stream.try_clone()?)), None),
+    };
+
+    match result {
+        // Nothing has been written yet, so the client gets the reason like for parse errors
+        Err(Error::Input(message)) => {
+            let mut stream = stream;
+            writeln!(stream, "{message}")
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Serve generator output over HTTP.
+/// Each generator is an endpoint and the query string holds its arguments.
+fn serve_http(addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = handle_http_client(stream) {
+                        eprintln!("Failed to serve client: {e}");
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to accept connection: {e}"),
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_http_client(mut stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_LEN));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Skip the headers, none of them change the response
+    loop {
+        let mut header = String::new();
+
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+
+    if method != "GET" {
+        return write_http_error(
+            &mut stream,
+            "405 Method Not Allowed",
+            "Only GET is supported\n",
+        );
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let cmd = percent_decode(path.trim_matches('/'));
+
+    if GenArgs::command().find_subcommand(&cmd).is_none() || cmd == "serve" {
+        return write_http_error(&mut stream, "404 Not Found", "Unknown endpoint\n");
+    }
+
+    let fields = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), Value::String(percent_decode(value)))
+        })
+        .collect();
+
+    let args = match fields_to_words(&cmd, fields).and_then(parse_words) {
+        Ok(args) => args,
+        Err(e) => return write_http_error(&mut stream, "400 Bad Request", &e),
+    };
+
+    let content_type = match args.commands {
+        Command::Unicode {
+            encoding: UnicodeEncoding::Utf16 | UnicodeEncoding::Utf32,
+            ..
+        } => "application/octet-stream",
+        _ => "text/plain; charset=utf-8",
+    };
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+    );
+
+    let writer = Arc::new(Mutex::new(ChunkedWriter {
+        inner: stream,
+        head: Some(head),
+    }));
+
+    let result = program::run(args, Arc::clone(&writer), None);
+    let mut writer = writer.lock().expect("Failed to lock writer");
+
+    match result {
+        // The arguments are checked before anything is generated
+        Err(Error::Input(message)) if writer.head.is_some() => write_http_error(
+            &mut writer.inner,
+            "400 Bad Request",
+            &format!("{message}\n"),
+        ),
+        result => {
+            result?;
+            writer.finish()
+        }
+    }
+}
+
+fn write_http_error(stream: &mut TcpStream, status: &str, message: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+        message.len()
+    )
+}
+
+/// Writes everything it's given as a chunk of an HTTP response
+/// using chunked transfer encoding.
+struct ChunkedWriter<W: Write> {
+    inner: W,
+    /// The status line and headers, which are only sent along with the first chunk
+    /// so that the response can still be an error until then.
+    head: Option<String>,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    fn write_head(&mut self) -> Result<()> {
+        match self.head.take() {
+            Some(head) => self.inner.write_all(head.as_bytes()),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the terminating chunk.
+    fn finish(&mut self) -> Result<()> {
+        self.write_head()?;
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Splice for ChunkedWriter<W> {}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        // An empty chunk would end the response
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.write_head()?;
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Parses a request which is either a JSON object (e.g. {"cmd":"int","range":"0..9"})
+/// or a command line (e.g. int 0..9 -n 100).
+pub fn parse_request(request: &str) -> std::result::Result<GenArgs, String> {
+    let request = request.trim();
+
+    let words = if request.starts_with('{') {
+        json_to_words(request)?
+    } else {
+        split_command_line(request)?
+    };
+
+    let words = match words.first().map(String::as_str) {
+        Some("gen") => words[1..].to_vec(),
+        _ => words,
+    };
+
+    parse_words(words)
+}
+
+fn parse_words(words: Vec<String>) -> std::result::Result<GenArgs, String> {
+    let args = GenArgs::try_parse_from(std::iter::once("gen".to_owned()).chain(words))
+        .map_err(|e| e.to_string())?;
+
+    // Clients must not be able to write files or block the server
+    if args.destination.is_some() || args.daemon {
+        return Err("--destination and --daemon are not allowed in requests\n".to_owned());
+    }
+
+    if let Command::Serve { .. } | Command::Tree { .. } = args.commands {
+        return Err("serve and tree are not allowed in requests\n".to_owned());
+    }
+
+    if let Command::Template { file: Some(_), .. } = args.commands {
+        return Err("Template files are not allowed in requests\n".to_owned());
+    }
+
+    check_limits(&args.commands)?;
+
+    Ok(args)
+}
+
+/// Rejects requests which would take more than their share of the server.
+fn check_limits(command: &Command) -> std::result::Result<(), String> {
+    let total = if command.is_sized() { "size" } else { "amount" };
+
+    // Durations are compared in whole seconds, rounded up
+    let duration = command
+        .duration()
+        .map(|duration| duration.as_secs_f64().ceil() as usize);
+
+    let limits = [
+        ("threads", command.threads(), MAX_THREADS, ""),
+        (total, command.total(), MAX_TOTAL, ""),
+        ("buf-size", command.buf_size(), MAX_BUF_SIZE, ""),
+        ("duration", duration, MAX_DURATION.as_secs() as usize, "s"),
+    ];
+
+    for (name, value, max, unit) in limits {
+        if value.is_some_and(|value| value > max) {
+            return Err(format!("{name} must be at most {max}{unit} in requests\n"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns a JSON request into command line words.
+/// `cmd` is the subcommand, positional arguments are matched by name
+/// and every other key becomes a flag.
+fn json_to_words(request: &str) -> std::result::Result<Vec<String>, String> {
+    let request: Value =
+        serde_json::from_str(request).map_err(|e| format!("Invalid JSON request: {e}\n"))?;
+
+    let Value::Object(mut fields) = request else {
+        return Err("JSON request must be an object\n".to_owned());
+    };
+
+    let Some(Value::String(cmd)) = fields.remove("cmd") else {
+        return Err("JSON request must contain a \"cmd\" string\n".to_owned());
+    };
+
+    fields_to_words(&cmd, fields.into_iter().collect())
+}
+
+/// Turns named arguments into command line words for the subcommand `cmd`.
+/// Positional arguments are matched by name and every other key becomes a flag,
+/// either by its long name (e.g. amount) or its short name (e.g. n).
+fn fields_to_words(
+    cmd: &str,
+    fields: Vec<(String, Value)>,
+) -> std::result::Result<Vec<String>, String> {
+    let command = GenArgs::command();
+
+    let subcommand = command
+        .find_subcommand(cmd)
+        .ok_or_else(|| format!("Unknown command {cmd:?}\n"))?;
+
+    let mut words = vec![cmd.to_owned()];
+    let mut positionals = vec![];
+
+    for positional in subcommand.get_positionals() {
+        if let Some((_, value)) = fields
+            .iter()
+            .find(|(key, _)| key == positional.get_id().as_str())
+        {
+            positionals.push(json_value_to_string(value)?);
+        }
+    }
+
+    for (key, value) in fields {
+        if subcommand
+            .get_positionals()
+            .any(|positional| positional.get_id().as_str() == key)
+        {
+            continue;
+        }
+
+        let Some(arg) = find_flag(subcommand, &key) else {
+            return Err(format!(
+                "Unknown argument {:?} for {cmd}\n",
+                key.replace('_', "-")
+            ));
+        };
+
+        words.extend(flag_words(arg, value)?);
+    }
+
+    // Positional values like -5..5 would otherwise be mistaken for flags
+    if !positionals.is_empty() {
+        words.push("--".to_owned());
+        words.extend(positionals);
+    }
+
+    Ok(words)
+}
+
+/// The flag of `command` named `key`,
+/// either by its long name (e.g. amount) or its short name (e.g. n).
+pub fn find_flag<'a>(command: &'a clap::Command, key: &str) -> Option<&'a clap::Arg> {
+    let key = key.replace('_', "-");
+
+    command.get_arguments().find(|arg| {
+        !arg.is_positional()
+            && (arg.get_long() == Some(key.as_str())
+                || arg
+                    .get_short()
+                    .is_some_and(|short| key == short.to_string()))
+    })
+}
+
+/// The command line words which set `arg` to `value`.
+pub fn flag_words(arg: &clap::Arg, value: Value) -> std::result::Result<Vec<String>, String> {
+    let flag = format!("--{}", arg.get_long().unwrap_or(arg.get_id().as_str()));
+    let mut words = vec![];
+
+    // Flags like --progress don't take a value
+    if !arg.get_action().takes_values() {
+        match value {
+            Value::Bool(true) => words.push(flag),
+            Value::String(s) if s.is_empty() || s == "true" => words.push(flag),
+            _ => {}
+        }
+
+        return Ok(words);
+    }
+
+    // Flags with an optional value like --query are given without one for true
+    let optional = arg.get_num_args().is_some_and(|n| n.min_values() == 0);
+
+    match value {
+        Value::Null => {}
+        Value::Bool(true) if optional => words.push(flag),
+        Value::String(s) if optional && (s.is_empty() || s == "true") => words.push(flag),
+        Value::Array(values) => {
+            for value in values {
+                words.push(format!("{flag}={}", json_value_to_string(&value)?));
+            }
+        }
+        value => words.push(format!("{flag}={}", json_value_to_string(&value)?)),
+    }
+
+    Ok(words)
+}
+
+pub fn json_value_to_string(value: &Value) -> std::result::Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!("Unsupported value in request: {value}\n")),
+    }
+}
+
+/// Decodes a percent-encoded URL component where `+` is a space.
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+
+    while let Some(b) = iter.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = iter.clone().take(2).collect();
+
+                match std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(decoded) if hex.len() == 2 => {
+                        bytes.push(decoded);
+                        iter.nth(1);
+                    }
+                    _ => bytes.push(b),
+                }
+            }
+            _ => bytes.push(b),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Splits a command line into words.
+/// Supports single quotes, double quotes and backslash escapes.
+pub fn split_command_line(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => word.push(c),
+            ('\\', _) => {
+                word.push(chars.next().ok_or("Trailing backslash in request\n")?);
+                in_word = true;
+            }
+            (q, None) if q == '\'' || q == '"' => {
+                quote = Some(q);
+                in_word = true;
+            }
+            (q, Some(open)) if q == open => quote = None,
+            (c, None) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (c, _) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err("Unterminated quote in request\n".to_owned());
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{parse_request, percent_decode, split_command_line, ChunkedWriter};
+    use crate::args::Command;
+
+    #[test]
+    fn command_line_requests() {
+        let args = parse_request("int 0..9 -n 100\n").unwrap();
+        assert!(matches!(args.commands, Command::Int { .. }));
+        assert_eq!(args.commands.total(), Some(100));
+
+        let args = parse_request("gen ascii --size 1KiB --charset 'a b'").unwrap();
+        assert_eq!(args.commands.total(), Some(1024));
+    }
+
+    #[test]
+    fn json_requests() {
+        let args = parse_request(r#"{"cmd":"int","range":"-5..5","amount":100}"#).unwrap();
+        assert!(matches!(args.commands, Command::Int { .. }));
+        assert_eq!(args.commands.total(), Some(100));
+
+        let args = parse_request(r#"{"cmd":"float","n":3,"progress":true}"#).unwrap();
+        assert_eq!(args.commands.total(), Some(3));
+
+        assert!(parse_request(r#"{"range":"0..9"}"#).is_err());
+        assert!(parse_request(r#"{"cmd":"int","nope":1}"#).is_err());
+        assert!(parse_request(r#"["int"]"#).is_err());
+    }
+
+    #[test]
+    fn forbidden_requests() {
+        for request in [
+            "-d out.txt int",
+            "--daemon int",
+            "tree /tmp/tree",
+            "serve --socket /tmp/gen.sock",
+            "template --file secrets.txt",
+        ] {
+            assert!(parse_request(request).is_err(), "{request}");
+        }
+    }
+
+    #[test]
+    fn request_limits() {
+        assert!(parse_request("int -t 16 -n 1B -b 16M").is_ok());
+        assert!(parse_request("ascii --duration 10m").is_ok());
+
+        for (request, message) in [
+            ("int -t 17", "threads must be at most 16"),
+            ("uuid -n 2B", "amount must be at most"),
+            ("bytes -s 2GB", "size must be at most"),
+            ("ascii --buf-size 1GiB", "buf-size must be at most"),
+            ("float -b 1B", "buf-size must be at most"),
+            ("int --duration 1000h", "duration must be at most 600s"),
+            ("bytes --duration 600001ms", "duration must be at most 600s"),
+        ] {
+            let error = parse_request(request).unwrap_err();
+            assert!(error.starts_with(message), "{request}: {error}");
+        }
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("0..99"), "0..99");
+        assert_eq!(percent_decode("a+b%20c"), "a b c");
+        assert_eq!(percent_decode("%2e%2E"), "..");
+        assert_eq!(percent_decode("%C3%A9"), "é");
+
+        // Invalid escapes are kept as they are
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn command_line_splitting() {
+        let words = |line| split_command_line(line).unwrap();
+
+        assert_eq!(words("  int  0..9 -n 5 "), ["int", "0..9", "-n", "5"]);
+        assert_eq!(words(r#"a "b c" 'd e'"#), ["a", "b c", "d e"]);
+        assert_eq!(words(r#"a\ b "c\"d" 'e\f'"#), ["a b", "c\"d", "e\\f"]);
+        assert_eq!(words(r#"'' """#), ["", ""]);
+        assert!(words("").is_empty());
+
+        assert!(split_command_line("'open").is_err());
+        assert!(split_command_line("trailing\\").is_err());
+    }
+
+    #[test]
+    fn chunked_writer() {
+        let mut writer = ChunkedWriter {
+            inner: Vec::new(),
+            head: Some("HEAD\r\n\r\n".to_owned()),
+        };
+
+        // Nothing is sent, not even the head, until there is something to send
+        assert_eq!(writer.write(b"").unwrap(), 0);
+        assert!(writer.inner.is_empty());
+
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(&[b'x'; 26]).unwrap();
+        writer.finish().unwrap();
+
+        let expected = format!(
+            "HEAD\r\n\r\n5\r\nhello\r\n1a\r\n{}\r\n0\r\n\r\n",
+            "x".repeat(26)
+        );
+        assert_eq!(String::from_utf8(writer.inner).unwrap(), expected);
+
+        // An empty response still has the head
+        let mut writer = ChunkedWriter {
+            inner: Vec::new(),
+            head: Some("HEAD\r\n\r\n".to_owned()),
+        };
+
+        writer.finish().unwrap();
+        assert_eq!(writer.inner, b"HEAD\r\n\r\n0\r\n\r\n");
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    io::{self, Result, Write},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::splice::Splice;
+
+/// Splits everything written to it into UDP datagrams of a fixed size.
+/// Optionally limits the number of datagrams sent per second.
+pub struct UdpSink {
+    socket: UdpSocket,
+    datagram_size: usize,
+    interval: Option<Duration>,
+    next_send: Instant,
+    buffer: Vec<u8>,
+}
+
+impl UdpSink {
+    pub fn connect(
+        addr: impl ToSocketAddrs,
+        datagram_size: usize,
+        packets_per_second: Option<u32>,
+    ) -> Result<Self> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Address did not resolve")
+        })?;
+
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        Ok(UdpSink {
+            socket,
+            datagram_size: datagram_size.max(1),
+            interval: packets_per_second.map(|pps| Duration::from_secs(1) / pps),
+            next_send: Instant::now(),
+            buffer: Vec::with_capacity(datagram_size),
+        })
+    }
+
+    fn send(&mut self, datagram: &[u8]) -> Result<()> {
+        if let Some(interval) = self.interval {
+            let now = Instant::now();
+
+            if self.next_send > now {
+                thread::sleep(self.next_send - now);
+            }
+
+            self.next_send = self.next_send.max(now) + interval;
+        }
+
+        match self.socket.send(datagram) {
+            // Nothing is listening on the other end yet, which is fine for UDP
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+impl Splice for UdpSink {}
+
+impl Write for UdpSink {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut remaining = buf;
+
+        // Send straight from the input when nothing is buffered
+        while self.buffer.is_empty() && remaining.len() >= self.datagram_size {
+            let (datagram, rest) = remaining.split_at(self.datagram_size);
+            self.send(datagram)?;
+            remaining = rest;
+        }
+
+        for chunk in remaining.chunks(self.datagram_size) {
+            let missing = self.datagram_size - self.buffer.len();
+            let (head, tail) = chunk.split_at(missing.min(chunk.len()));
+            self.buffer.extend_from_slice(head);
+
+            if self.buffer.len() == self.datagram_size {
+                let datagram = std::mem::take(&mut self.buffer);
+                self.send(&datagram)?;
+                self.buffer = datagram;
+                self.buffer.clear();
+            }
+
+            self.buffer.extend_from_slice(tail);
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Sends whatever is left as a smaller datagram.
+    fn flush(&mut self) -> Result<()> {
+        if !self.buffer.is_empty() {
+            let datagram = std::mem::take(&mut self.buffer);
+            self.send(&datagram)?;
+            self.buffer = datagram;
+            self.buffer.clear();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::UdpSocket,
+        time::{Duration, Instant},
+    };
+
+    use super::UdpSink;
+
+    fn receiver() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    fn receive(socket: &UdpSocket, count: usize) -> Vec<Vec<u8>> {
+        let mut buffer = [0; 1024];
+
+        (0..count)
+            .map(|_| {
+                let len = socket.recv(&mut buffer).unwrap();
+                buffer[..len].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixed_size_datagrams() {
+        let receiver = receiver();
+        let mut sink = UdpSink::connect(receiver.local_addr().unwrap(), 10, None).unwrap();
+        let data: Vec<u8> = (0..35).collect();
+
+        // Writes which don't line up with the datagrams
+        for piece in [&data[..3], &data[3..20], &data[20..21], &data[21..]] {
+            sink.write_all(piece).unwrap();
+        }
+
+        sink.flush().unwrap();
+
+        let datagrams = receive(&receiver, 4);
+        let sizes: Vec<_> = datagrams.iter().map(Vec::len).collect();
+
+        assert_eq!(sizes, [10, 10, 10, 5]);
+        assert_eq!(datagrams.concat(), data);
+    }
+
+    #[test]
+    fn flush_without_data() {
+        let receiver = receiver();
+        let mut sink = UdpSink::connect(receiver.local_addr().unwrap(), 10, None).unwrap();
+
+        sink.flush().unwrap();
+        sink.write_all(b"abc").unwrap();
+        sink.flush().unwrap();
+        sink.flush().unwrap();
+
+        // Only the one datagram with data was sent
+        assert_eq!(receive(&receiver, 1), [b"abc"]);
+
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+
+        assert!(receiver.recv(&mut [0; 16]).is_err());
+    }
+
+    #[test]
+    fn packets_per_second() {
+        let receiver = receiver();
+        let mut sink = UdpSink::connect(receiver.local_addr().unwrap(), 4, Some(100)).unwrap();
+        let start = Instant::now();
+
+        // The first datagram goes right away, then one every 10ms
+        sink.write_all(&[0; 4 * 6]).unwrap();
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(45), "{elapsed:?}");
+        assert_eq!(receive(&receiver, 6).len(), 6);
+    }
+
+    #[test]
+    fn nothing_listening() {
+        // A port which was just free
+        let addr = receiver().local_addr().unwrap();
+        let mut sink = UdpSink::connect(addr, 8, None).unwrap();
+
+        for _ in 0..10 {
+            sink.write_all(&[1; 20]).unwrap();
+            sink.flush().unwrap();
+        }
+    }
+}
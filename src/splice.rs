@@ -0,0 +1,257 @@
+use std::{fs, io::Stdout, net::TcpStream};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+#[cfg(target_os = "linux")]
+use std::{
+    collections::VecDeque,
+    io::{self, IoSlice, Result, Write},
+    mem,
+    os::unix::io::{AsRawFd, RawFd},
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+#[cfg(target_os = "linux")]
+use crate::pipeline::{self, Job};
+
+/// The size to grow a pipe to, which is as large as an unprivileged process can make it by default.
+/// A larger pipe holds more buffers at once, so the writer and the reader wait less for each other.
+#[cfg(target_os = "linux")]
+const PIPE_SIZE: libc::c_int = 1024 * 1024;
+
+/// How often to check how much of the spliced buffers the reader has taken
+/// while waiting for it.
+#[cfg(target_os = "linux")]
+const RELEASE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Writers which can be handed buffers without copying them when they are a pipe.
+pub trait Splice {
+    /// The pipe to splice buffers into, if the writer is one.
+    #[cfg(target_os = "linux")]
+    fn pipe(&self) -> Option<RawFd> {
+        None
+    }
+}
+
+impl Splice for Stdout {
+    #[cfg(target_os = "linux")]
+    fn pipe(&self) -> Option<RawFd> {
+        pipe(self.as_raw_fd())
+    }
+}
+
+impl Splice for fs::File {
+    #[cfg(target_os = "linux")]
+    fn pipe(&self) -> Option<RawFd> {
+        pipe(self.as_raw_fd())
+    }
+}
+
+impl Splice for TcpStream {}
+
+#[cfg(unix)]
+impl Splice for UnixStream {}
+
+/// The file descriptor if it's a pipe. Regular files and terminals are written as usual.
+#[cfg(target_os = "linux")]
+fn pipe(fd: RawFd) -> Option<RawFd> {
+    let mut stat = mem::MaybeUninit::<libc::stat>::uninit();
+
+    if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+
+    let stat = unsafe { stat.assume_init() };
+    (stat.st_mode & libc::S_IFMT == libc::S_IFIFO).then_some(fd)
+}
+
+/// Writes the buffers of the pipeline with `vmsplice`, which maps them into the pipe
+/// instead of copying them.
+///
+/// The pipe refers to the memory of a buffer until the reader has taken it, so a buffer
+/// is only handed back to its generator thread, or freed, once the pipe has been drained past it.
+/// If the pipe ends up holding more than was spliced into it, because something else writes
+/// to it as well, the rest is written by copying instead.
+#[cfg(target_os = "linux")]
+pub fn write_jobs<T: Write>(writer: &Mutex<T>, pipe: RawFd, jobs: &Receiver<Job>) -> Result<()> {
+    // Anything the writer buffered has to come first
+    writer.lock().expect("Failed to lock writer").flush()?;
+
+    // Failing to grow the pipe only makes splicing slower
+    unsafe { libc::fcntl(pipe, libc::F_SETPIPE_SZ, PIPE_SIZE) };
+
+    let mut batch = Vec::with_capacity(pipeline::MAX_BATCH);
+    let mut spliced = Spliced::default();
+
+    loop {
+        // Keep checking for buffers the reader has taken while there are any,
+        // since the generator threads may be waiting for them
+        let timeout = (!spliced.held.is_empty()).then_some(RELEASE_INTERVAL);
+
+        match pipeline::receive_batch(jobs, &mut batch, timeout) {
+            Ok(()) => {}
+            Err(RecvTimeoutError::Timeout) => {
+                if !spliced.release(pipe, true)? {
+                    return pipeline::write_jobs(writer, jobs);
+                }
+
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let mut slices: Vec<IoSlice> = batch
+            .iter()
+            .map(|(buffer, _)| IoSlice::new(buffer))
+            .collect();
+
+        // Whatever is already in the pipe is read before anything spliced into it
+        if spliced.ahead.is_none() {
+            spliced.ahead = Some(unread(pipe)?);
+        }
+
+        let result = vmsplice_all(pipe, &mut slices);
+
+        // Even a failed splice may have put part of the batch into the pipe
+        for (buffer, returns) in batch.drain(..) {
+            spliced.total += buffer.len();
+            spliced.held.push_back((spliced.total, buffer, returns));
+        }
+
+        result?;
+
+        if !spliced.release(pipe, false)? {
+            return pipeline::write_jobs(writer, jobs);
+        }
+    }
+
+    while !spliced.held.is_empty() {
+        thread::sleep(RELEASE_INTERVAL);
+        spliced.release(pipe, true)?;
+    }
+
+    Ok(())
+}
+
+/// The buffers which are still in the pipe.
+///
+/// Buffers which haven't been released when it's dropped, because writing failed,
+/// are leaked, since the pipe may still refer to them.
+#[cfg(target_os = "linux")]
+#[derive(Default)]
+struct Spliced {
+    /// How many bytes were in the pipe before the first splice.
+    ahead: Option<usize>,
+    /// How many bytes have been spliced into the pipe so far.
+    total: usize,
+    /// Each buffer with the total at its end.
+    held: VecDeque<(usize, Vec<u8>, Sender<Vec<u8>>)>,
+}
+
+#[cfg(target_os = "linux")]
+impl Spliced {
+    /// Hands back the buffers the reader has taken.
+    /// Returns false, and abandons the buffers, if that can't be told
+    /// from the amount left in the pipe since something else has written to it.
+    /// When `waiting` for the reader, fails if it has gone away,
+    /// since the rest of the pipe will never be read then.
+    fn release(&mut self, pipe: RawFd, waiting: bool) -> Result<bool> {
+        let unread = unread(pipe)?;
+
+        let ahead = self.ahead.unwrap_or(0);
+
+        let Some(read) = (ahead + self.total).checked_sub(unread) else {
+            self.abandon();
+            return Ok(false);
+        };
+
+        let taken = read.saturating_sub(ahead);
+
+        while let Some((end, ..)) = self.held.front() {
+            if *end > taken {
+                break;
+            }
+
+            let (_, buffer, returns) = self.held.pop_front().expect("Checked above");
+            pipeline::return_buffer(buffer, &returns);
+        }
+
+        if waiting && !self.held.is_empty() && reader_gone(pipe) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        Ok(true)
+    }
+
+    /// Leaks the buffers which may still be in the pipe
+    /// and hands their generator threads new ones in their place.
+    fn abandon(&mut self) {
+        for (_, buffer, returns) in self.held.drain(..) {
+            pipeline::return_buffer(Vec::with_capacity(buffer.capacity()), &returns);
+            mem::forget(buffer);
+        }
+    }
+}
+
+/// How many bytes are waiting to be read from the pipe.
+#[cfg(target_os = "linux")]
+fn unread(pipe: RawFd) -> Result<usize> {
+    let mut unread: libc::c_int = 0;
+
+    if unsafe { libc::ioctl(pipe, libc::FIONREAD, &mut unread) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unread as usize)
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Spliced {
+    fn drop(&mut self) {
+        for (_, buffer, _) in self.held.drain(..) {
+            mem::forget(buffer);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reader_gone(pipe: RawFd) -> bool {
+    let mut poll = libc::pollfd {
+        fd: pipe,
+        events: 0,
+        revents: 0,
+    };
+
+    let ready = unsafe { libc::poll(&mut poll, 1, 0) };
+    ready > 0 && poll.revents & libc::POLLERR != 0
+}
+
+#[cfg(target_os = "linux")]
+fn vmsplice_all(pipe: RawFd, mut slices: &mut [IoSlice]) -> Result<()> {
+    IoSlice::advance_slices(&mut slices, 0);
+
+    while !slices.is_empty() {
+        // IoSlice is ABI compatible with iovec
+        let spliced = unsafe { libc::vmsplice(pipe, slices.as_ptr().cast(), slices.len(), 0) };
+
+        match spliced {
+            -1 => {
+                let e = io::Error::last_os_error();
+
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            spliced => IoSlice::advance_slices(&mut slices, spliced as usize),
+        }
+    }
+
+    Ok(())
+}
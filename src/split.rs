@@ -0,0 +1,244 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{
+    args::{Command, GenArgs, UnicodeEncoding},
+    error::{Error, Result},
+};
+
+const DEFAULT_PATTERN: &str = "{path}.{n:4}";
+
+/// Writes the output into several files.
+/// Each file is generated by a single worker thread with its own writer,
+/// so the workers never contend on a shared writer.
+pub fn run(path: &Path, args: &GenArgs) -> Result<()> {
+    let per_file = match (&args.split_size, &args.split_lines) {
+        (Some(_), _) if !args.commands.is_sized() => {
+            return Err(Error::input(
+                "--split-size only applies to ascii, bytes and unicode, use --split-lines instead",
+            ));
+        }
+        (_, Some(_)) if args.commands.is_sized() => {
+            return Err(Error::input(
+                "--split-lines only applies to int, float, uuid, url, template and regex, use --split-size instead",
+            ));
+        }
+        (Some(size), _) => Some(size.bytes),
+        (_, Some(lines)) => Some(lines.get()),
+        (None, None) => None,
+    };
+
+    if args.commands.duration().is_some() {
+        return Err(Error::input(
+            "--duration can't be used when splitting the output",
+        ));
+    }
+
+    // Unicode files must hold whole code units
+    let granularity = match &args.commands {
+        Command::Unicode { encoding, .. } => match encoding {
+            UnicodeEncoding::Utf8 => 1,
+            UnicodeEncoding::Utf16 => 2,
+            UnicodeEncoding::Utf32 => 4,
+        },
+        _ => 1,
+    };
+
+    let shards = shard_sizes(
+        args.commands.total(),
+        per_file,
+        args.files.map(|f| f.get()),
+        granularity,
+    )?;
+
+    let pattern = args.split_pattern.as_deref().unwrap_or(DEFAULT_PATTERN);
+
+    // Also fails before anything is written if the pattern is invalid
+    if let Some(parent) = file_name(pattern, path, 1)?.parent() {
+        crate::create_dirs(parent)?;
+    }
+
+    let next = AtomicUsize::new(0);
+    let workers = num_cpus::get().min(shards.len());
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+
+                    let Some(&total) = shards.get(index) else {
+                        return Ok(());
+                    };
+
+                    let mut args = args.clone();
+                    args.commands = args.commands.shard(total);
+
+                    crate::run_file(&file_name(pattern, path, index + 1)?, &args)?;
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("Thread panicked"))
+    })
+}
+
+/// The number of values or bytes to write to each file.
+pub fn shard_sizes(
+    total: Option<usize>,
+    per_file: Option<usize>,
+    files: Option<usize>,
+    granularity: usize,
+) -> Result<Vec<usize>> {
+    match (total, per_file, files) {
+        (Some(_), Some(_), Some(_)) => Err(Error::input(
+            "--files can't be combined with both a size or amount and --split-size or --split-lines",
+        )),
+        (_, Some(per_file), _) if per_file < granularity => Err(Error::input(format!(
+            "--split-size must be at least {granularity}B"
+        ))),
+        (Some(total), Some(per_file), None) => {
+            // Round down so every full file holds whole code units
+            let per_file = per_file - per_file % granularity;
+            let mut shards = vec![per_file; total / per_file];
+
+            if total % per_file != 0 {
+                shards.push(total % per_file);
+            }
+
+            Ok(shards)
+        }
+        (None, Some(per_file), Some(files)) => {
+            Ok(vec![per_file - per_file % granularity; files])
+        }
+        (Some(total), None, Some(files)) => {
+            let units = total / granularity;
+
+            if units < files {
+                return Err(Error::input(format!(
+                    "Can't split {total} into {files} files"
+                )));
+            }
+
+            let mut shards: Vec<usize> = (0..files)
+                .map(|i| (units / files + usize::from(i < units % files)) * granularity)
+                .collect();
+
+            // Leave it to the generator to complain about the size
+            // if it doesn't fit the encoding
+            shards[files - 1] += total % granularity;
+
+            Ok(shards)
+        }
+        (None, _, _) => Err(Error::input(
+            "Splitting the output requires a size or amount, or --files with --split-size or --split-lines",
+        )),
+        (Some(_), None, None) => unreachable!("Splitting requires one of the split arguments"),
+    }
+}
+
+/// Fills in the file number and destination in the pattern.
+pub fn file_name(pattern: &str, path: &Path, n: usize) -> Result<PathBuf> {
+    let mut name = pattern.replace("{path}", &path.to_string_lossy());
+
+    if !name.contains("{n") {
+        return Err(Error::input("--split-pattern must contain {n}"));
+    }
+
+    while let Some(start) = name.find("{n") {
+        let end = name[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| Error::input("Unclosed {n in --split-pattern"))?;
+
+        let width = match &name[start + 2..end] {
+            "" => 0,
+            spec => spec
+                .strip_prefix(':')
+                .and_then(|width| width.parse().ok())
+                .ok_or_else(|| Error::input("Invalid {n} width in --split-pattern"))?,
+        };
+
+        name.replace_range(start..=end, &format!("{n:0width$}"));
+    }
+
+    Ok(PathBuf::from(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{file_name, shard_sizes};
+
+    #[test]
+    fn split_file_names() {
+        let name = |pattern: &str, n: usize| file_name(pattern, Path::new("out.txt"), n);
+
+        assert_eq!(
+            name("{path}.{n:4}", 7).unwrap(),
+            PathBuf::from("out.txt.0007")
+        );
+        assert_eq!(
+            name("{path}-{n}.gz", 12).unwrap(),
+            PathBuf::from("out.txt-12.gz")
+        );
+        assert_eq!(
+            name("{n:2}/{n}-{path}", 3).unwrap(),
+            PathBuf::from("03/3-out.txt")
+        );
+
+        let error = |pattern: &str| name(pattern, 1).unwrap_err().to_string();
+        assert_eq!(error("{path}.part"), "--split-pattern must contain {n}");
+        assert_eq!(error("{path}.{n:4"), "Unclosed {n in --split-pattern");
+        assert_eq!(error("{path}.{n4}"), "Invalid {n} width in --split-pattern");
+        assert_eq!(
+            error("{path}.{n:x}"),
+            "Invalid {n} width in --split-pattern"
+        );
+    }
+
+    #[test]
+    fn split_shard_sizes() {
+        assert_eq!(
+            shard_sizes(Some(10), Some(3), None, 1).unwrap(),
+            [3, 3, 3, 1]
+        );
+        assert_eq!(shard_sizes(Some(9), Some(3), None, 1).unwrap(), [3, 3, 3]);
+        assert_eq!(shard_sizes(None, Some(5), Some(2), 1).unwrap(), [5, 5]);
+        assert_eq!(shard_sizes(Some(10), None, Some(3), 1).unwrap(), [4, 3, 3]);
+
+        // UTF-32 files hold whole code units and the last one gets the rest
+        assert_eq!(shard_sizes(Some(18), None, Some(2), 4).unwrap(), [8, 10]);
+        assert_eq!(
+            shard_sizes(Some(40), Some(10), None, 4).unwrap(),
+            [8, 8, 8, 8, 8]
+        );
+        assert_eq!(shard_sizes(None, Some(7), Some(2), 2).unwrap(), [6, 6]);
+
+        let error = |total, per_file, files| {
+            shard_sizes(total, per_file, files, 1)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(error(Some(2), None, Some(3)), "Can't split 2 into 3 files");
+        assert_eq!(
+            error(Some(10), Some(0), None),
+            "--split-size must be at least 1B"
+        );
+        assert_eq!(
+            shard_sizes(Some(8), Some(2), None, 4)
+                .unwrap_err()
+                .to_string(),
+            "--split-size must be at least 4B"
+        );
+        assert!(error(Some(10), Some(3), Some(2)).starts_with("--files can't be combined"));
+        assert!(error(None, None, Some(2)).starts_with("Splitting the output requires"));
+    }
+}
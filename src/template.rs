@@ -0,0 +1,290 @@
+use std::str::FromStr;
+
+use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
+use uuid::{Builder, Uuid};
+
+use crate::{
+    args::{CountRange, FloatRange, IntRange, UuidVersion},
+    charset::Charset,
+    format::Format,
+};
+
+const PLACEHOLDERS: &str = "Expected uuid, int, float, ascii or choice";
+
+/// A line of text with placeholders which are replaced by random values,
+/// e.g. `{{uuid}},{{int 18..90}},{{choice "red,green"}},{{ascii 8 alnum}}`.
+///
+/// A placeholder is the name of a generator followed by its arguments, separated by spaces.
+/// Arguments with spaces are written in double quotes.
+/// `\` escapes braces and itself, and `\n` and `\t` are a newline and a tab.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Uuid(UuidVersion),
+    Int(IntRange),
+    Float(FloatRange),
+    Ascii {
+        length: CountRange,
+        chars: Vec<char>,
+    },
+    Choice(Vec<String>),
+}
+
+impl Template {
+    /// The text with new random values for the placeholders.
+    pub fn render(&self, rng: &mut ThreadRng) -> String {
+        let mut buffer = Vec::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => buffer.extend_from_slice(text.as_bytes()),
+                Part::Uuid(version) => match version {
+                    UuidVersion::Empty => Uuid::nil(),
+                    UuidVersion::V4 => Builder::from_random_bytes(rng.gen()).into_uuid(),
+                    UuidVersion::V7 => Uuid::now_v7(),
+                    UuidVersion::Max => Uuid::max(),
+                }
+                .format(&mut buffer),
+                Part::Int(range) => rng.gen_range(range.min..=range.max).format(&mut buffer),
+                Part::Float(range) => rng.gen_range(range.min..=range.max).format(&mut buffer),
+                Part::Ascii { length, chars } => {
+                    for _ in 0..rng.gen_range(length.min..=length.max) {
+                        buffer.push(chars[rng.gen_range(0..chars.len())] as u8);
+                    }
+                }
+                Part::Choice(options) => {
+                    let option = options.choose(rng).expect("Choices aren't empty");
+                    buffer.extend_from_slice(option.as_bytes());
+                }
+            }
+        }
+
+        String::from_utf8(buffer).expect("Templates and values are UTF-8")
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = s.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => text.push('\n'),
+                    Some((_, 't')) => text.push('\t'),
+                    Some((_, c @ ('{' | '}' | '\\'))) => text.push(c),
+                    // Other backslashes are kept, e.g. in regular expressions or paths
+                    Some((_, c)) => {
+                        text.push('\\');
+                        text.push(c);
+                    }
+                    None => text.push('\\'),
+                },
+                '{' if chars.peek().is_some_and(|(_, c)| *c == '{') => {
+                    chars.next();
+
+                    let start = i + 2;
+                    let Some(len) = s[start..].find("}}") else {
+                        return Err(format!(
+                            "The {{{{ at column {} is never closed, escape it as \\{{{{ to keep it",
+                            s[..i].chars().count() + 1
+                        ));
+                    };
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+
+                    parts.push(parse_placeholder(&s[start..start + len])?);
+
+                    // Skip the placeholder and the closing braces
+                    while chars.next_if(|(j, _)| *j < start + len + 2).is_some() {}
+                }
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Template { parts })
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Part, String> {
+    let words = split_words(placeholder)?;
+
+    let Some((name, args)) = words.split_first() else {
+        return Err(format!("Empty placeholder {{{{}}}}. {PLACEHOLDERS}"));
+    };
+
+    let max_args = match name.as_str() {
+        "ascii" => 2,
+        "choice" => usize::MAX,
+        _ => 1,
+    };
+
+    if args.len() > max_args {
+        return Err(format!(
+            "Too many arguments in {{{{{}}}}}",
+            placeholder.trim()
+        ));
+    }
+
+    let arg = args.first().map(String::as_str);
+
+    let part = match name.as_str() {
+        "uuid" => Part::Uuid(match arg {
+            Some(version) => version.parse().map_err(|_| {
+                format!("Unknown uuid version {version}, expected empty, v4, v7 or max")
+            })?,
+            None => UuidVersion::V4,
+        }),
+        "int" => Part::Int(match arg {
+            Some(range) => range.parse()?,
+            None => IntRange { min: 0, max: 99 },
+        }),
+        "float" => Part::Float(match arg {
+            Some(range) => range.parse()?,
+            None => FloatRange { min: 0.0, max: 1.0 },
+        }),
+        "ascii" => {
+            let length = match arg {
+                Some(length) => length.parse().map_err(|_| {
+                    format!("Invalid length {length} for ascii, expected a number or a range like 8..16")
+                })?,
+                None => CountRange { min: 8, max: 8 },
+            };
+
+            let charset = args.get(1).map_or("alnum", String::as_str);
+
+            let chars: Vec<char> = charset
+                .parse::<Charset>()
+                .map_err(|_| format!("Invalid charset {charset} for ascii"))?
+                .into_chars()
+                .into_iter()
+                .filter(char::is_ascii)
+                .collect();
+
+            if chars.is_empty() {
+                return Err(format!("The charset {charset} has no ASCII characters"));
+            }
+
+            Part::Ascii { length, chars }
+        }
+        "choice" => {
+            let options: Vec<String> = match args {
+                [options] => options.split(',').map(str::to_owned).collect(),
+                options => options.to_vec(),
+            };
+
+            if options.is_empty() {
+                return Err("choice needs options, e.g. {{choice \"red,green\"}}".to_owned());
+            }
+
+            Part::Choice(options)
+        }
+        name => return Err(format!("Unknown placeholder {name}. {PLACEHOLDERS}")),
+    };
+
+    Ok(part)
+}
+
+/// Splits the inside of a placeholder on spaces, except in double quotes.
+fn split_words(s: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word = None::<String>;
+    let mut quoted = false;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            '\\' if quoted => {
+                let escaped = chars.next().ok_or("Unterminated quote in placeholder")?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quoted {
+        return Err(format!("Unterminated quote in {{{{{}}}}}", s.trim()));
+    }
+
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+
+    fn render(template: &str) -> String {
+        template
+            .parse::<Template>()
+            .unwrap()
+            .render(&mut rand::thread_rng())
+    }
+
+    #[test]
+    fn template_placeholders() {
+        let regex = ::regex::Regex::new(
+            r"\A(\d+),(red|green),\d{4}\t[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[0-9a-f]{4}-[0-9a-f]{12},(a b|c)\z",
+        )
+        .unwrap();
+
+        for _ in 0..100 {
+            let line = render(
+                r#"{{int 18..90}},{{choice "red,green"}},{{ascii 4 digits}}\t{{uuid}},{{choice "a b" c}}"#,
+            );
+            let captures = regex.captures(&line).expect(&line);
+
+            assert!((18..=90).contains(&captures[1].parse::<i64>().unwrap()));
+        }
+
+        assert_eq!(
+            render("{{uuid empty}}"),
+            "00000000-0000-0000-0000-000000000000"
+        );
+    }
+
+    #[test]
+    fn template_escapes() {
+        assert_eq!(render(r"\{{int\}} a\\b\n\q { }"), "{{int}} a\\b\n\\q { }");
+        assert_eq!(render(r"{{choice x\}}"), "x\\");
+    }
+
+    #[test]
+    fn template_errors() {
+        let error = |s: &str| s.parse::<Template>().unwrap_err();
+
+        assert!(error("id {{int").starts_with("The {{ at column 4 is never closed"));
+        assert!(error("{{ }}").starts_with("Empty placeholder"));
+        assert!(error("{{date}}").starts_with("Unknown placeholder date"));
+        assert_eq!(
+            error("{{int 1..2 3}}"),
+            "Too many arguments in {{int 1..2 3}}"
+        );
+        assert!(error(r#"{{choice "a}}"#).starts_with("Unterminated quote"));
+        assert!(error("{{int 5..1}}").contains("did you mean 1..5?"));
+        assert!(error("{{ascii x}}").starts_with("Invalid length x for ascii"));
+        assert!(error("{{ascii 4 [:foo:]}}").starts_with("Invalid charset"));
+        assert!(error("{{ascii 4 \u{e9}}}").contains("has no ASCII characters"));
+        assert!(error("{{uuid v9}}").starts_with("Unknown uuid version v9"));
+    }
+}
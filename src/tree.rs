@@ -0,0 +1,465 @@
+use std::{
+    fs, io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use rand::Rng;
+
+use crate::{
+    args::{ByteSizeRange, Command, CountRange, Distribution, GenArgs},
+    compress::{compression, CompressedWriter},
+    error::{Error, Result},
+    program::{self, FileContent},
+};
+
+/// How many names to try before giving up on finding one which isn't taken.
+const MAX_NAME_ATTEMPTS: usize = 100;
+
+/// Creates a random directory tree and fills it with random files.
+/// The directories are created up front and the files are
+/// written by worker threads which each take one directory at a time.
+pub fn run(args: &GenArgs) -> Result<()> {
+    let Command::Tree {
+        dir,
+        depth,
+        fan_out,
+        files,
+        size,
+        distribution,
+        name_charset,
+        name_length,
+        extension,
+        content,
+        threads,
+        progress,
+    } = &args.commands
+    else {
+        unreachable!("Only the tree command creates trees");
+    };
+
+    // Every option which writes elsewhere requires a destination
+    if args.destination.is_some() {
+        return Err(Error::input(
+            "tree writes into its own directory and can't be used with --destination, --daemon or the --split options",
+        ));
+    }
+
+    let depth = depth.unwrap_or(3);
+    let fan_out = fan_out.unwrap_or(CountRange { min: 2, max: 4 });
+    let files = files.map_or(100, |f| f.get());
+    let size = size.unwrap_or(ByteSizeRange {
+        min: 1000,
+        max: 100 * 1000,
+    });
+    let distribution = distribution.unwrap_or(Distribution::Uniform);
+
+    let content = match content {
+        Some(content) => content.clone(),
+        None => "ascii --printable-only"
+            .parse()
+            .expect("The default content is valid"),
+    };
+
+    // Set up once, since large unicode charsets take a while
+    let content = FileContent::new(*content.0)?;
+
+    // Characters which can't be part of a file name
+    let mut chars: Vec<char> = name_charset.clone().map_or_else(
+        || ('a'..='z').chain('0'..='9').collect(),
+        |charset| charset.into_chars(),
+    );
+
+    chars.retain(|c| *c != '/' && *c != '\\' && *c != '\0');
+
+    if chars.is_empty() {
+        return Err(Error::input(
+            "The name charset has no characters which can be used in file names",
+        ));
+    }
+
+    let compression = compression(args);
+
+    // Compressed files get the usual suffix, e.g. notes.txt.gz
+    let extension = match (extension, compression.and_then(|c| c.extension())) {
+        (Some(extension), Some(suffix)) => Some(format!("{extension}.{suffix}")),
+        (None, suffix) => suffix.map(str::to_owned),
+        (extension, None) => extension.clone(),
+    };
+
+    let names = Names {
+        chars,
+        length: name_length.unwrap_or(CountRange { min: 4, max: 12 }),
+        extension,
+    };
+
+    if names.length.max == 0 {
+        return Err(Error::input("The name length must be greater than 0"));
+    }
+
+    // Names of only dots would have to be longer than . and ..
+    if names.chars.iter().all(|c| *c == '.') && names.length.max <= 2 {
+        return Err(Error::input(
+            "Names of only dots must be longer than 2 characters, use a larger --name-charset or --name-length",
+        ));
+    }
+
+    crate::create_dirs(dir)?;
+
+    let mut rng = rand::thread_rng();
+    let dirs = create_tree(dir, depth, fan_out, &names, &mut rng)?;
+
+    let mut file_counts = vec![0; dirs.len()];
+
+    for _ in 0..files {
+        file_counts[rng.gen_range(0..dirs.len())] += 1;
+    }
+
+    let progress_bar = if *progress {
+        Some(program::create_progress_bar_amount(files as u64))
+    } else {
+        None
+    };
+
+    let next = AtomicUsize::new(0);
+    let num_threads = threads.map_or_else(num_cpus::get, NonZeroUsize::get);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads.min(dirs.len()))
+            .map(|_| {
+                scope.spawn(|| -> Result<()> {
+                    let mut rng = rand::thread_rng();
+                    let mut deduplicator = content.deduplicator();
+                    let mut buffer = Vec::new();
+
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+
+                        let Some(dir) = dirs.get(index) else {
+                            return Ok(());
+                        };
+
+                        for _ in 0..file_counts[index] {
+                            let file_size = file_size(size, distribution, &mut rng);
+                            let mut file = create_unique(dir, &names, true, &mut rng, |path| {
+                                fs::OpenOptions::new()
+                                    .write(true)
+                                    .create_new(true)
+                                    .open(path)
+                            })?;
+
+                            match compression {
+                                Some(compression) => {
+                                    let mut writer = CompressedWriter::new(file, compression);
+
+                                    content.write(
+                                        file_size,
+                                        &mut writer,
+                                        &mut deduplicator,
+                                        &mut rng,
+                                        &mut buffer,
+                                    )?;
+
+                                    writer.finish()?;
+                                }
+                                None => content.write(
+                                    file_size,
+                                    &mut file,
+                                    &mut deduplicator,
+                                    &mut rng,
+                                    &mut buffer,
+                                )?,
+                            }
+
+                            if let Some(progress_bar) = &progress_bar {
+                                progress_bar.inc(1);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("Thread panicked"))
+    })?;
+
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+    }
+
+    content.finish();
+
+    Ok(())
+}
+
+struct Names {
+    chars: Vec<char>,
+    length: CountRange,
+    extension: Option<String>,
+}
+
+impl Names {
+    fn generate(&self, rng: &mut impl Rng, with_extension: bool) -> String {
+        loop {
+            let length = rng.gen_range(self.length.min.max(1)..=self.length.max);
+
+            let mut name: String = (0..length)
+                .map(|_| self.chars[rng.gen_range(0..self.chars.len())])
+                .collect();
+
+            if name == "." || name == ".." {
+                continue;
+            }
+
+            if let (true, Some(extension)) = (with_extension, &self.extension) {
+                name.push('.');
+                name.push_str(extension);
+            }
+
+            return name;
+        }
+    }
+}
+
+/// Creates the directories level by level and returns all of them, including the root.
+fn create_tree(
+    root: &Path,
+    depth: usize,
+    fan_out: CountRange,
+    names: &Names,
+    rng: &mut impl Rng,
+) -> Result<Vec<PathBuf>> {
+    let mut dirs = vec![root.to_path_buf()];
+    let mut level = 0..1;
+
+    for _ in 0..depth {
+        let start = dirs.len();
+
+        for parent in level {
+            for _ in 0..rng.gen_range(fan_out.min..=fan_out.max) {
+                let dir = create_unique(&dirs[parent].clone(), names, false, rng, |path| {
+                    fs::create_dir(path).map(|_| path.to_path_buf())
+                })?;
+
+                dirs.push(dir);
+            }
+        }
+
+        level = start..dirs.len();
+    }
+
+    Ok(dirs)
+}
+
+/// Creates a file or directory with a random name which isn't taken yet.
+fn create_unique<T>(
+    dir: &Path,
+    names: &Names,
+    is_file: bool,
+    rng: &mut impl Rng,
+    create: impl Fn(&Path) -> io::Result<T>,
+) -> Result<T> {
+    for _ in 0..MAX_NAME_ATTEMPTS {
+        let path = dir.join(names.generate(rng, is_file));
+
+        match create(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => return result.map_err(|e| Error::destination(path.display(), e)),
+        }
+    }
+
+    Err(Error::input(format!(
+        "Ran out of unique names in {dir:?}, use a larger --name-charset or --name-length"
+    )))
+}
+
+fn file_size(size: ByteSizeRange, distribution: Distribution, rng: &mut impl Rng) -> usize {
+    match distribution {
+        Distribution::Uniform => rng.gen_range(size.min..=size.max),
+        Distribution::Log => {
+            // Uniform over the logarithm of the size, shifted by one to allow empty files
+            let min = (size.min as f64 + 1.0).ln();
+            let max = (size.max as f64 + 1.0).ln();
+
+            let size_plus_one = rng.gen_range(min..=max).exp().round() as usize;
+            size_plus_one.clamp(size.min + 1, size.max + 1) - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Read,
+        path::{Path, PathBuf},
+    };
+
+    use clap::Parser;
+    use flate2::read::GzDecoder;
+
+    use crate::args::GenArgs;
+
+    /// A fresh directory for each test, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("gen-tree-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn run(args: &[&str], dir: &Path) -> crate::error::Result<()> {
+        let dir = dir.to_str().unwrap();
+        let args = ["gen"].iter().chain(args).map(|a| a.replace("{dir}", dir));
+        super::run(&GenArgs::try_parse_from(args).unwrap())
+    }
+
+    /// Returns the directories at each depth and all files below `dir`.
+    fn walk(dir: &Path) -> (Vec<usize>, Vec<PathBuf>) {
+        let mut levels = Vec::new();
+        let mut files = Vec::new();
+        let mut level = vec![dir.to_path_buf()];
+
+        while !level.is_empty() {
+            let mut next = Vec::new();
+
+            for dir in level {
+                for entry in fs::read_dir(dir).unwrap() {
+                    let path = entry.unwrap().path();
+
+                    if path.is_dir() {
+                        next.push(path);
+                    } else {
+                        files.push(path);
+                    }
+                }
+            }
+
+            if !next.is_empty() {
+                levels.push(next.len());
+            }
+
+            level = next;
+        }
+
+        (levels, files)
+    }
+
+    #[test]
+    fn tree_shape() {
+        let dir = TempDir::new("shape");
+        let args = [
+            "tree",
+            "{dir}",
+            "--depth",
+            "2",
+            "--fan-out",
+            "3",
+            "-n",
+            "20",
+            "-s",
+            "10B",
+            "--extension",
+            "txt",
+            "--content",
+            "ascii --charset digits",
+        ];
+
+        run(&args, &dir.0).unwrap();
+        let (levels, files) = walk(&dir.0);
+
+        assert_eq!(levels, [3, 9]);
+        assert_eq!(files.len(), 20);
+
+        for file in files {
+            assert_eq!(file.extension().unwrap(), "txt");
+
+            let content = fs::read_to_string(file).unwrap();
+            assert_eq!(content.len(), 10);
+            assert!(content.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn compressed_tree() {
+        let dir = TempDir::new("compressed");
+        let args = [
+            "--compress",
+            "gzip",
+            "tree",
+            "{dir}",
+            "--depth",
+            "1",
+            "-n",
+            "5",
+            "-s",
+            "1KiB",
+            "--extension",
+            "txt",
+            "--content",
+            "ascii --charset lower",
+        ];
+
+        run(&args, &dir.0).unwrap();
+        let (_, files) = walk(&dir.0);
+
+        assert_eq!(files.len(), 5);
+
+        for file in files {
+            assert!(file.to_str().unwrap().ends_with(".txt.gz"));
+
+            let mut content = String::new();
+            GzDecoder::new(fs::File::open(file).unwrap())
+                .read_to_string(&mut content)
+                .unwrap();
+
+            assert_eq!(content.len(), 1024);
+            assert!(content.chars().all(|c| c.is_ascii_lowercase()));
+        }
+    }
+
+    #[test]
+    fn dot_names() {
+        let dir = TempDir::new("dots");
+        let args = [
+            "tree",
+            "{dir}",
+            "--name-charset",
+            ".",
+            "--name-length",
+            "1..2",
+        ];
+
+        let error = run(&args, &dir.0).unwrap_err();
+        assert!(error.to_string().starts_with("Names of only dots"));
+    }
+    #[test]
+    fn rejects_destinations() {
+        let dir = TempDir::new("destination");
+
+        for args in [
+            &["-d", "out", "tree", "{dir}"][..],
+            &["-d", "out", "--daemon", "tree", "{dir}"],
+            &["-d", "out", "--split-size", "1KB", "tree", "{dir}"],
+        ] {
+            let error = run(args, &dir.0).unwrap_err();
+            assert!(error
+                .to_string()
+                .contains("can't be used with --destination"));
+            assert!(!dir.0.exists());
+        }
+    }
+}
@@ -0,0 +1,191 @@
+#[rustfmt::skip]
+mod block;
+#[rustfmt::skip]
+mod general_category;
+#[rustfmt::skip]
+mod script;
+
+use std::str::FromStr;
+
+/// Abbreviation, long name and ranges of a property value.
+type Property = (&'static str, &'static str, &'static [(char, char)]);
+
+/// A Unicode script, matched by name (e.g. Greek) or abbreviation (e.g. Grek).
+#[derive(Debug, Clone, Copy)]
+pub struct Script {
+    ranges: &'static [(char, char)],
+}
+
+impl Script {
+    pub fn contains(&self, c: char) -> bool {
+        ranges_contain(self.ranges, c)
+    }
+}
+
+impl FromStr for Script {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = normalize(s);
+
+        script::BY_NAME
+            .iter()
+            .find(|(short, long, _)| normalize(short) == name || normalize(long) == name)
+            .map(|(_, _, ranges)| Script { ranges })
+            .ok_or_else(|| {
+                format!(
+                    "Unknown script {s}, expected a name like Greek or an abbreviation like Grek"
+                )
+            })
+    }
+}
+
+/// A Unicode block, matched by name (e.g. CJK Unified Ideographs).
+#[derive(Debug, Clone, Copy)]
+pub struct Block {
+    first: u32,
+    last: u32,
+}
+
+impl Block {
+    pub fn contains(&self, c: char) -> bool {
+        (self.first..=self.last).contains(&(c as u32))
+    }
+}
+
+impl FromStr for Block {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = normalize(s);
+
+        block::BY_NAME
+            .iter()
+            .find(|(block, _, _)| normalize(block) == name)
+            .map(|(_, first, last)| Block {
+                first: *first,
+                last: *last,
+            })
+            .ok_or_else(|| {
+                format!(
+                    "Unknown block {s}, expected a name like Basic Latin or CJK Unified Ideographs"
+                )
+            })
+    }
+}
+
+/// A Unicode general category.
+/// Either a single category (e.g. Nd or Decimal_Number)
+/// or a group of categories (e.g. L or Letter).
+#[derive(Debug, Clone)]
+pub struct GeneralCategory {
+    ranges: Vec<&'static [(char, char)]>,
+}
+
+impl GeneralCategory {
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|ranges| ranges_contain(ranges, c))
+    }
+}
+
+impl FromStr for GeneralCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = normalize(s);
+
+        if let Some((_, _, ranges)) = general_category::BY_NAME
+            .iter()
+            .find(|(short, long, _)| normalize(short) == name || normalize(long) == name)
+        {
+            return Ok(GeneralCategory {
+                ranges: vec![ranges],
+            });
+        }
+
+        let group: &[&str] = match name.as_str() {
+            "l" | "letter" => &["Lu", "Ll", "Lt", "Lm", "Lo"],
+            "lc" | "casedletter" => &["Lu", "Ll", "Lt"],
+            "m" | "mark" | "combiningmark" => &["Mn", "Mc", "Me"],
+            "n" | "number" => &["Nd", "Nl", "No"],
+            "p" | "punctuation" => &["Pc", "Pd", "Ps", "Pe", "Pi", "Pf", "Po"],
+            "s" | "symbol" => &["Sm", "Sc", "Sk", "So"],
+            "z" | "separator" => &["Zs", "Zl", "Zp"],
+            "c" | "other" => &["Cc", "Cf", "Cs", "Co", "Cn"],
+            _ => {
+                return Err(format!(
+                    "Unknown category {s}, expected a name like Nd or Decimal_Number, or a group like L or Letter"
+                ))
+            }
+        };
+
+        Ok(GeneralCategory {
+            ranges: general_category::BY_NAME
+                .iter()
+                .filter(|(short, _, _)| group.contains(short))
+                .map(|(_, _, ranges)| *ranges)
+                .collect(),
+        })
+    }
+}
+
+/// Whether the character has been assigned in the Unicode version of the tables.
+pub fn is_assigned(c: char) -> bool {
+    !ranges_contain(general_category::UNASSIGNED, c)
+}
+
+fn ranges_contain(ranges: &[(char, char)], c: char) -> bool {
+    ranges
+        .binary_search_by(|(first, last)| {
+            if c < *first {
+                std::cmp::Ordering::Greater
+            } else if c > *last {
+                std::cmp::Ordering::Less
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// Loose matching of property names as described in UAX #44.
+/// Case, whitespace, underscores and hyphens are ignored.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace() && *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, GeneralCategory, Script};
+
+    #[test]
+    fn loose_names() {
+        assert!(" greek".parse::<Script>().unwrap().contains('λ'));
+        assert!("GREK".parse::<Script>().unwrap().contains('λ'));
+        assert!("basic-latin".parse::<Block>().unwrap().contains('a'));
+        assert!("decimal number"
+            .parse::<GeneralCategory>()
+            .unwrap()
+            .contains('7'));
+        assert!("L".parse::<GeneralCategory>().unwrap().contains('λ'));
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(
+            "Foo".parse::<Script>().unwrap_err(),
+            "Unknown script Foo, expected a name like Greek or an abbreviation like Grek"
+        );
+        assert!("Foo"
+            .parse::<Block>()
+            .unwrap_err()
+            .starts_with("Unknown block Foo, expected a name like Basic Latin"));
+        assert!("Foo"
+            .parse::<GeneralCategory>()
+            .unwrap_err()
+            .contains("or a group like L or Letter"));
+    }
+}
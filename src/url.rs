@@ -0,0 +1,421 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
+
+use crate::args::{CountRange, Scheme};
+
+/// Common generic and country code top-level domains,
+/// including second-level domains which are used like them.
+pub const TLDS: &[&str] = &[
+    "com", "org", "net", "edu", "gov", "info", "biz", "io", "dev", "app", "ai", "co", "me", "tv",
+    "xyz", "online", "shop", "tech", "site", "cloud", "de", "fr", "nl", "be", "ch", "at", "es",
+    "it", "pl", "se", "no", "dk", "fi", "cz", "pt", "ie", "eu", "uk", "co.uk", "org.uk", "ru",
+    "ua", "tr", "jp", "co.jp", "cn", "com.cn", "kr", "co.kr", "in", "co.in", "sg", "au", "com.au",
+    "nz", "co.nz", "za", "co.za", "br", "com.br", "ar", "com.ar", "mx", "com.mx", "ca", "us",
+];
+
+/// Ports which come up more often than the rest.
+const COMMON_PORTS: &[u16] = &[21, 80, 443, 3000, 5000, 8000, 8080, 8443, 9000];
+
+/// Lowercase letters of a few scripts for internationalized domain names.
+const IDN_SCRIPTS: &[&str] = &[
+    "äöüéèêàçñåøæ",
+    "абвгдежзийклмнопрстуфхцчшщыэюя",
+    "αβγδεζηθικλμνξοπρστυφχψω",
+    "日本語中文字网站例测试",
+];
+
+/// Characters which have to be percent-encoded in paths and queries.
+const RESERVED: &[char] = &[' ', '/', '?', '#', '&', '=', '+', '%', 'é', 'ü', '€', '日'];
+
+/// The shape of the urls to generate.
+#[derive(Debug, Clone)]
+pub struct UrlOptions {
+    /// The length of the generated strings, or 5..15 characters.
+    pub length: Option<usize>,
+    pub schemes: Vec<Scheme>,
+    pub tlds: Vec<String>,
+    pub subdomains: CountRange,
+    /// The number of path segments.
+    pub segments: Option<u8>,
+    /// The number of key=value pairs.
+    pub query: Option<CountRange>,
+    /// The fractions of urls with these parts.
+    pub port: f64,
+    pub userinfo: f64,
+    pub fragment: f64,
+    pub ip: f64,
+    pub idn: f64,
+    pub percent_encoded: f64,
+}
+
+impl UrlOptions {
+    pub fn generate(&self, rng: &mut ThreadRng) -> String {
+        let scheme = self.schemes.choose(rng).expect("Schemes aren't empty");
+        let mut url = format!("{}://", scheme.as_str());
+
+        // file urls are local, e.g. file:///srv/data
+        if *scheme != Scheme::File {
+            if rng.gen_bool(self.userinfo) {
+                url += &self.word(rng);
+
+                if rng.gen_bool(0.5) {
+                    url.push(':');
+                    url += &self.word(rng);
+                }
+
+                url.push('@');
+            }
+
+            url += &self.host(rng);
+
+            if rng.gen_bool(self.port) {
+                let port = if rng.gen_bool(0.5) {
+                    *COMMON_PORTS.choose(rng).expect("Ports aren't empty")
+                } else {
+                    rng.gen_range(1024..=u16::MAX)
+                };
+
+                url += &format!(":{port}");
+            }
+        }
+
+        let segments = match self.segments {
+            Some(segments) => segments,
+            None if *scheme == Scheme::File => 1,
+            None => 0,
+        };
+
+        for _ in 0..segments {
+            url.push('/');
+            url += &self.component(rng);
+        }
+
+        if let Some(query) = self.query {
+            if segments == 0 {
+                url.push('/');
+            }
+
+            let pairs: Vec<String> = (0..rng.gen_range(query.min..=query.max))
+                .map(|_| format!("{}={}", self.word(rng), self.component(rng)))
+                .collect();
+
+            url.push('?');
+            url += &pairs.join("&");
+        }
+
+        if rng.gen_bool(self.fragment) {
+            url.push('#');
+            url += &self.word(rng);
+        }
+
+        url
+    }
+
+    fn host(&self, rng: &mut ThreadRng) -> String {
+        if rng.gen_bool(self.ip) {
+            return if rng.gen_bool(0.5) {
+                Ipv4Addr::from(rng.gen::<[u8; 4]>()).to_string()
+            } else {
+                format!("[{}]", ipv6(rng))
+            };
+        }
+
+        let mut labels = vec![];
+
+        for _ in 0..rng.gen_range(self.subdomains.min..=self.subdomains.max) {
+            labels.push(self.word(rng));
+        }
+
+        if rng.gen_bool(self.idn) {
+            labels.push(punycode(&self.idn_label(rng)));
+        } else {
+            labels.push(self.word(rng));
+        }
+
+        labels.push(self.tlds.choose(rng).expect("TLDs aren't empty").clone());
+        labels.join(".")
+    }
+
+    fn len(&self, rng: &mut ThreadRng) -> usize {
+        self.length.unwrap_or_else(|| rng.gen_range(5..15))
+    }
+
+    /// Lowercase ASCII letters.
+    fn word(&self, rng: &mut ThreadRng) -> String {
+        (0..self.len(rng))
+            .map(|_| rng.gen_range(b'a'..=b'z') as char)
+            .collect()
+    }
+
+    /// A path segment or query value, sometimes with characters which are percent-encoded.
+    fn component(&self, rng: &mut ThreadRng) -> String {
+        let mut chars: Vec<char> = self.word(rng).chars().collect();
+
+        if rng.gen_bool(self.percent_encoded) {
+            for _ in 0..rng.gen_range(1..=3) {
+                let i = rng.gen_range(0..=chars.len());
+                chars.insert(i, *RESERVED.choose(rng).expect("Characters aren't empty"));
+            }
+        }
+
+        percent_encode(&chars.into_iter().collect::<String>())
+    }
+
+    /// Mostly letters of a single script mixed with some ASCII letters.
+    fn idn_label(&self, rng: &mut ThreadRng) -> String {
+        let script: Vec<char> = IDN_SCRIPTS
+            .choose(rng)
+            .expect("Scripts aren't empty")
+            .chars()
+            .collect();
+
+        let mut label: String = (0..self.len(rng))
+            .map(|_| {
+                if rng.gen_bool(0.7) {
+                    *script.choose(rng).expect("Scripts aren't empty")
+                } else {
+                    rng.gen_range(b'a'..=b'z') as char
+                }
+            })
+            .collect();
+
+        // A label of only ASCII letters isn't internationalized
+        if label.is_ascii() {
+            label.push(*script.choose(rng).expect("Scripts aren't empty"));
+        }
+
+        label
+    }
+}
+
+/// A random IPv6 address, sometimes with a run of zeros which is written as ::.
+fn ipv6(rng: &mut ThreadRng) -> Ipv6Addr {
+    let mut segments: [u16; 8] = rng.gen();
+
+    if rng.gen_bool(0.5) {
+        let start = rng.gen_range(0..8);
+        let end = rng.gen_range(start..8);
+        segments[start..=end].fill(0);
+    }
+
+    Ipv6Addr::from(segments)
+}
+
+/// Encodes everything except the unreserved characters A-Z, a-z, 0-9, -, ., _ and ~.
+pub fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded += &format!("%{byte:02X}"),
+        }
+    }
+
+    encoded
+}
+
+/// The ASCII form of a lowercase domain label, e.g. xn--bcher-kva for bücher (RFC 3492).
+pub fn punycode(label: &str) -> String {
+    const BASE: u32 = 36;
+    const T_MIN: u32 = 1;
+    const T_MAX: u32 = 26;
+
+    let code_points: Vec<u32> = label.chars().map(u32::from).collect();
+    let mut output: String = label.chars().filter(char::is_ascii).collect();
+    let basic = output.len() as u32;
+
+    if basic > 0 {
+        output.push('-');
+    }
+
+    let mut n = 0x80;
+    let mut delta = 0;
+    let mut bias = 72;
+    let mut handled = basic;
+
+    while (handled as usize) < code_points.len() {
+        let next = *code_points
+            .iter()
+            .filter(|c| **c >= n)
+            .min()
+            .expect("Some code points aren't handled yet");
+
+        delta += (next - n) * (handled + 1);
+        n = next;
+
+        for &c in &code_points {
+            if c < n {
+                delta += 1;
+            }
+
+            if c != n {
+                continue;
+            }
+
+            let mut q = delta;
+            let mut k = BASE;
+
+            loop {
+                let t = k.saturating_sub(bias).clamp(T_MIN, T_MAX);
+
+                if q < t {
+                    break;
+                }
+
+                output.push(punycode_digit(t + (q - t) % (BASE - t)));
+                q = (q - t) / (BASE - t);
+                k += BASE;
+            }
+
+            output.push(punycode_digit(q));
+            bias = adapt(delta, handled + 1, handled == basic);
+            delta = 0;
+            handled += 1;
+        }
+
+        delta += 1;
+        n += 1;
+    }
+
+    format!("xn--{output}")
+}
+
+fn adapt(delta: u32, num_points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / 700 } else { delta / 2 };
+    delta += delta / num_points;
+
+    let mut k = 0;
+
+    while delta > (35 * 26) / 2 {
+        delta /= 35;
+        k += 36;
+    }
+
+    k + (36 * delta) / (delta + 38)
+}
+
+fn punycode_digit(d: u32) -> char {
+    match d {
+        0..=25 => (b'a' + d as u8) as char,
+        _ => (b'0' + (d - 26) as u8) as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use clap::Parser;
+
+    use super::{percent_encode, punycode, UrlOptions};
+    use crate::args::{CountRange, GenArgs, Scheme};
+
+    #[test]
+    fn url_punycode() {
+        assert_eq!(punycode("bücher"), "xn--bcher-kva");
+        assert_eq!(punycode("münchen"), "xn--mnchen-3ya");
+        assert_eq!(punycode("пример"), "xn--e1afmkfd");
+        assert_eq!(punycode("日本語"), "xn--wgv71a119e");
+    }
+
+    #[test]
+    fn url_percent_encode() {
+        assert_eq!(percent_encode("a-z_0.9~"), "a-z_0.9~");
+        assert_eq!(percent_encode("a b/?é"), "a%20b%2F%3F%C3%A9");
+    }
+
+    fn url_options() -> UrlOptions {
+        UrlOptions {
+            length: Some(5),
+            schemes: vec![Scheme::Https],
+            tlds: vec!["co.uk".to_owned()],
+            subdomains: CountRange { min: 1, max: 1 },
+            segments: None,
+            query: None,
+            port: 0.0,
+            userinfo: 0.0,
+            fragment: 0.0,
+            ip: 0.0,
+            idn: 0.0,
+            percent_encoded: 0.0,
+        }
+    }
+
+    fn assert_urls(options: &UrlOptions, pattern: &str) -> Vec<String> {
+        let regex = ::regex::Regex::new(&format!(r"\A(?:{pattern})\z")).unwrap();
+        let mut rng = rand::thread_rng();
+
+        (0..100)
+            .map(|_| {
+                let url = options.generate(&mut rng);
+                assert!(regex.is_match(&url), "{url} doesn't match {pattern}");
+                url
+            })
+            .collect()
+    }
+
+    #[test]
+    fn url_parts() {
+        let mut options = url_options();
+        assert_urls(&options, r"https://[a-z]{5}\.[a-z]{5}\.co\.uk");
+
+        options.port = 1.0;
+        options.userinfo = 1.0;
+        options.fragment = 1.0;
+        options.segments = Some(2);
+        options.query = Some(CountRange { min: 2, max: 2 });
+
+        let port = r"https://[a-z]{5}(:[a-z]{5})?@[a-z]{5}\.[a-z]{5}\.co\.uk:(\d+)";
+        let path = r"/[a-z]{5}/[a-z]{5}\?[a-z]{5}=[a-z]{5}&[a-z]{5}=[a-z]{5}#[a-z]{5}";
+
+        for url in assert_urls(&options, &format!("{port}{path}")) {
+            let port = url.rsplit_once(':').unwrap().1.split('/').next().unwrap();
+            assert!(port.parse::<u16>().is_ok_and(|port| port > 0), "{url}");
+        }
+
+        options.query = Some(CountRange { min: 0, max: 1 });
+        options.segments = Some(0);
+        options.percent_encoded = 1.0;
+        options.port = 0.0;
+        options.userinfo = 0.0;
+        options.fragment = 0.0;
+
+        let percent = r"([a-z]|%[0-9A-F]{2})+";
+        assert_urls(
+            &options,
+            &format!(r"https://[a-z]{{5}}\.[a-z]{{5}}\.co\.uk/\?([a-z]{{5}}={percent})?"),
+        );
+    }
+
+    #[test]
+    fn url_hosts() {
+        let mut options = url_options();
+        options.schemes = vec![Scheme::File];
+        assert_urls(&options, "file:///[a-z]{5}");
+
+        options.schemes = vec![Scheme::Https];
+        options.idn = 1.0;
+        assert_urls(&options, r"https://[a-z]{5}\.xn--[a-z0-9-]+\.co\.uk");
+
+        options.ip = 1.0;
+
+        for url in assert_urls(&options, r"https://\S+") {
+            let host = url.strip_prefix("https://").unwrap();
+
+            match host.strip_prefix('[') {
+                Some(host) => assert!(host.trim_end_matches(']').parse::<Ipv6Addr>().is_ok()),
+                None => assert!(host.parse::<Ipv4Addr>().is_ok()),
+            }
+        }
+    }
+
+    #[test]
+    fn url_labels_are_never_empty() {
+        assert!(GenArgs::try_parse_from(["gen", "url", "-l", "0"]).is_err());
+        assert!(GenArgs::try_parse_from(["gen", "url", "-l", "1"]).is_ok());
+    }
+}